@@ -9,10 +9,13 @@ use pancurses::initscr;
 struct Context {
     uint_value: u32,
     pwm: bool,
+    duty: u32,
+    pwm_mode: usize,
 }
 static MAIN_MENU: MenuItem<'_, Context> = MenuItem {
     name: "Main",
     hint: None,
+    access: AccessLevel::User,
     parent: None,
     menu_type: MenuItemType::SubMenu(&[&SUB1, &SUB2, &SUB3], |_| true)
 };
@@ -20,6 +23,7 @@ static MAIN_MENU: MenuItem<'_, Context> = MenuItem {
 static BOOL_VAL: MenuItem<'_, Context> = MenuItem {
     name: "PWM Read",
     hint: Some("boolean"),
+    access: AccessLevel::User,
     parent: None,
     menu_type: MenuItemType::ReadValue(|buf, ctx| { let _ = write!(buf, "{}", ctx.pwm); } ),
 };
@@ -27,6 +31,7 @@ static BOOL_VAL: MenuItem<'_, Context> = MenuItem {
 static UINT_VAL: MenuItem<'_, Context> = MenuItem {
     name: "Uint",
     hint: None,
+    access: AccessLevel::User,
     parent: None,
     menu_type: MenuItemType::ReadValue(|buf, ctx| { let _ = write!(buf, "{}", ctx.uint_value); } ),
 };
@@ -34,6 +39,7 @@ static UINT_VAL: MenuItem<'_, Context> = MenuItem {
 static UINT_VAL_WRITE: MenuItem<'_, Context> = MenuItem {
     name: "Uint_Write",
     hint: Some("u32"),
+    access: AccessLevel::User,
     parent: Some(&SUB2),
     menu_type: MenuItemType::WriteValue(|buf, ctx| {
         let _ = write!(buf, "{}", ctx.uint_value);
@@ -48,31 +54,100 @@ static UINT_VAL_WRITE: MenuItem<'_, Context> = MenuItem {
 static PWM_TEST: MenuItem<'_, Context> = MenuItem {
     name: "PWM Test",
     hint: None,
+    access: AccessLevel::User,
     parent: Some(&SUB1),
-    menu_type: MenuItemType::ExecValue(|buf, ctx| {
-        let _ = write!(buf, "{}", match ctx.pwm { false => "off", true => "on", } );
-    },
-    |ctx| ctx.pwm = !ctx.pwm
-    ),
+    menu_type: MenuItemType::Toggle(Toggle {
+        labels: None,
+        confirm: Confirm::YesNo,
+        flag: Flag {
+            get: |ctx| ctx.pwm,
+            set: |ctx, pwm| {
+                ctx.pwm = pwm;
+                Ok( () )
+            },
+        },
+    }),
+};
+
+static PWM_DUTY: MenuItem<'_, Context> = MenuItem {
+    name: "PWM Duty",
+    hint: None,
+    access: AccessLevel::User,
+    parent: Some(&SUB1),
+    menu_type: MenuItemType::TypedValue(TypedValue::Uint(Number {
+        min: 0,
+        max: 100,
+        step: 5,
+        unit: Some("%"),
+        get: |ctx| ctx.duty,
+        set: |ctx, duty| {
+            ctx.duty = duty;
+            Ok( () )
+        },
+    })),
+};
+
+static PWM_MODE: MenuItem<'_, Context> = MenuItem {
+    name: "PWM Mode",
+    hint: None,
+    access: AccessLevel::User,
+    parent: Some(&SUB1),
+    menu_type: MenuItemType::Select(Choice {
+        choices: &["Edge Aligned", "Center Aligned", "One Pulse"],
+        get: |ctx| ctx.pwm_mode,
+        set: |ctx, mode| {
+            ctx.pwm_mode = mode;
+            Ok( () )
+        },
+    }),
 };
 
 static SUB1: MenuItem<'_, Context> = MenuItem {
     name: "Sub Menu 1",
     hint: None,
+    access: AccessLevel::User,
     parent: Some(&MAIN_MENU),
-    menu_type: MenuItemType::SubMenu(&[&BOOL_VAL, &PWM_TEST], |_| true)
+    menu_type: MenuItemType::SubMenu(&[&BOOL_VAL, &PWM_TEST, &PWM_DUTY, &PWM_MODE], |_| true)
+};
+
+static UINT_RESET: MenuItem<'_, Context> = MenuItem {
+    name: "Uint_Reset",
+    hint: None,
+    access: AccessLevel::Service,
+    parent: Some(&SUB2),
+    menu_type: MenuItemType::ExecValue(|buf, ctx| {
+        let _ = write!(buf, "{}", ctx.uint_value);
+    },
+    |ctx| ctx.uint_value = 0,
+    Confirm::TypeName),
+};
+
+static WIFI_KEY: MenuItem<'_, Context> = MenuItem {
+    name: "WiFi Key",
+    hint: Some("8..32 characters"),
+    access: AccessLevel::User,
+    parent: Some(&SUB2),
+    menu_type: MenuItemType::Secret(|buf, _ctx| {
+        if buf.len() < 8 {
+            return Err(CallbackError::Custom("too short"));
+        }
+        Ok( () )
+    },
+    Mask::Stars),
 };
 
 static SUB2: MenuItem<'_, Context> = MenuItem {
     name: "Sub Menu 2",
     hint: None,
+    access: AccessLevel::User,
     parent: Some(&MAIN_MENU),
-    menu_type: MenuItemType::SubMenu(&[&UINT_VAL, &UINT_VAL_WRITE], |_| true)
+    menu_type: MenuItemType::SubMenu(&[&UINT_VAL, &UINT_VAL_WRITE, &UINT_RESET, &WIFI_KEY], |_| true)
 };
 
 static SUB3: MenuItem<'_, Context> = MenuItem {
     name: "Sub Menu 3",
     hint: None,
+    access: AccessLevel::User,
     parent: Some(&MAIN_MENU),
     menu_type: MenuItemType::SubMenu(&[&UINT_VAL2], |_| false)
 };
@@ -80,6 +155,7 @@ static SUB3: MenuItem<'_, Context> = MenuItem {
 static UINT_VAL2: MenuItem<'_, Context> = MenuItem {
     name: "Uint",
     hint: None,
+    access: AccessLevel::User,
     parent: None,
     menu_type: MenuItemType::ReadValue(|buf, ctx| { let _ = write!(buf, "{}", ctx.uint_value); } ),
 };
@@ -192,9 +268,22 @@ impl<'a> embedded_hal::serial::Write<u8> for SerialMock {
 }
 
 fn main() {
-    let mut context = Context { uint_value: 32, pwm: false, };
+    let mut context = Context { uint_value: 32, pwm: false, duty: 50, pwm_mode: 0, };
+
+    let start = std::time::Instant::now();
+    let clock = move || start.elapsed().as_millis() as u32;
 
-    let mut runner = Dispatcher::new(&MAIN_MENU).with_refresh();
+    let mut runner = Dispatcher::new(&MAIN_MENU).with_refresh(&clock, 1000).with_login(Login {
+        check: |password, _| match password {
+            "service" => Some(AccessLevel::Service),
+            "factory" => Some(AccessLevel::Factory),
+            _ => None,
+        },
+        clock: &clock,
+        timeout_ms: 5 * 60 * 1000,
+        max_attempts: 3,
+        lockout_ms: 30 * 1000,
+    });
 
     /* Setup ncurses. */
     let window = initscr();
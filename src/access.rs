@@ -0,0 +1,158 @@
+//! Access Control
+//!
+//! Items can require an access level above the default user level. The level is raised by logging in with a
+//! password, and falls back to the user level after a period of inactivity. Repeated failed logins lock the login out
+//! for a while.
+//!
+//! Independent of a login, an idle timeout returns the menu to the root when nobody has typed anything for a while.
+
+use core::fmt::{Display, Formatter};
+
+/// Access levels, from least to most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccessLevel {
+    User,
+    Service,
+    Factory,
+}
+impl Display for AccessLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::User => f.write_str("user"),
+            Self::Service => f.write_str("service"),
+            Self::Factory => f.write_str("factory"),
+        }
+    }
+}
+
+/// A monotonic time source in milliseconds, which is allowed to wrap.
+pub trait Clock {
+    fn now_ms(&self) -> u32;
+}
+impl<F: Fn() -> u32> Clock for F {
+    fn now_ms(&self) -> u32 {
+        self()
+    }
+}
+
+/// Login configuration.
+pub struct Login<'a, C> {
+    /// The access level granted by `password`, if any.
+    pub check: fn(password: &str, context: &C) -> Option<AccessLevel>,
+    pub clock: &'a dyn Clock,
+    /// Inactivity in milliseconds after which the session drops back to the user level, 0 never expires.
+    pub timeout_ms: u32,
+    /// Failed attempts in a row before logins are locked out.
+    pub max_attempts: u8,
+    /// How long logins stay locked out, in milliseconds.
+    pub lockout_ms: u32,
+}
+
+/// Idle timeout configuration.
+pub struct Idle<'a> {
+    pub clock: &'a dyn Clock,
+    /// Inactivity in milliseconds after which the menu returns to the root.
+    pub timeout_ms: u32,
+    /// Also drop back to the user level.
+    pub logout: bool,
+}
+impl Idle<'_> {
+    /// True when nothing happened between `last_activity` and `now` for too long.
+    #[must_use]
+    pub const fn is_idle(&self, last_activity: u32, now: u32) -> bool {
+        now.wrapping_sub(last_activity) >= self.timeout_ms
+    }
+}
+
+/// Why a login did not succeed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denied {
+    Password,
+    LockedOut,
+}
+
+/// State of the current login session.
+pub struct Session {
+    level: AccessLevel,
+    failures: u8,
+    locked_at: Option<u32>,
+    last_activity: u32,
+}
+impl Session {
+    pub const fn new() -> Self {
+        Self { level: AccessLevel::User, failures: 0, locked_at: None, last_activity: 0 }
+    }
+
+    pub const fn level(&self) -> AccessLevel {
+        self.level
+    }
+
+    /// Drop back to the user level.
+    pub const fn logout(&mut self) {
+        self.level = AccessLevel::User;
+    }
+
+    /// Record activity at `now`.
+    pub const fn touch(&mut self, now: u32) {
+        self.last_activity = now;
+    }
+
+    /// Drop back to the user level when the session has been idle too long, returns true when it did.
+    pub fn expire<C>(&mut self, login: &Login<'_, C>, now: u32) -> bool {
+        if self.level == AccessLevel::User || login.timeout_ms == 0 || now.wrapping_sub(self.last_activity) < login.timeout_ms {
+            return false;
+        }
+        self.logout();
+        true
+    }
+
+    pub fn login<C>(&mut self, login: &Login<'_, C>, password: &str, ctx: &C, now: u32) -> Result<AccessLevel, Denied> {
+        if let Some(locked_at) = self.locked_at {
+            if now.wrapping_sub(locked_at) < login.lockout_ms {
+                return Err(Denied::LockedOut);
+            }
+            self.locked_at = None;
+        }
+
+        if let Some(level) = (login.check)(password, ctx) {
+            self.failures = 0;
+            self.level = level;
+            return Ok(level);
+        }
+
+        self.failures = self.failures.saturating_add(1);
+        if self.failures >= login.max_attempts {
+            self.failures = 0;
+            self.locked_at = Some(now);
+        }
+        Err(Denied::Password)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session() {
+        let login = Login {
+            check: |password, &()| if password == "1234" { Some(AccessLevel::Service) } else { None },
+            clock: &|| 0,
+            timeout_ms: 100,
+            max_attempts: 2,
+            lockout_ms: 1000,
+        };
+        let mut session = Session::new();
+
+        assert_eq!(session.login(&login, "1234", &(), 0), Ok(AccessLevel::Service));
+        assert!(!session.expire(&login, 99));
+        assert!(session.expire(&login, 100));
+        assert_eq!(session.level(), AccessLevel::User);
+
+        /* Locked out after two failures, even for the right password */
+        assert_eq!(session.login(&login, "0000", &(), 200), Err(Denied::Password));
+        assert_eq!(session.login(&login, "0000", &(), 200), Err(Denied::Password));
+        assert_eq!(session.login(&login, "1234", &(), 1199), Err(Denied::LockedOut));
+        assert_eq!(session.login(&login, "1234", &(), 1200), Ok(AccessLevel::Service));
+    }
+}
@@ -0,0 +1,279 @@
+//! Async Dispatcher
+//!
+//! Runs the same state machine as [`Dispatcher`] on top of the `embedded-io-async` traits. Input is awaited in chunks
+//! and handed to the dispatcher, the output it renders is collected and written out as a whole once the input has been
+//! consumed, or as soon as the output buffer fills up. A menu render that fills it carries on after it has been written
+//! out.
+
+use core::{convert::Infallible, future::{poll_fn, Future}, pin::pin, task::Poll};
+
+use embedded_hal::serial::{Read as HalRead, Write as HalWrite};
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+
+use crate::{Dispatcher, Word};
+
+/// Feeds the bytes received so far to the dispatcher and collects what it writes.
+struct Port<'b> {
+    input: &'b [u8],
+    output: &'b mut [u8],
+    len: usize,
+    /// The output buffer filled up, the dispatcher carries on once it has been written out.
+    full: bool,
+}
+impl HalRead<Word> for Port<'_> {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<Word, Self::Error> {
+        let (&byte, rest) = self.input.split_first().ok_or(nb::Error::WouldBlock)?;
+        self.input = rest;
+        Ok(byte)
+    }
+}
+impl HalWrite<Word> for Port<'_> {
+    type Error = Infallible;
+
+    fn write(&mut self, word: Word) -> nb::Result<(), Self::Error> {
+        let Some(slot) = self.output.get_mut(self.len) else {
+            self.full = true;
+            return Err(nb::Error::WouldBlock);
+        };
+        *slot = word;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A delay that never ends, for running without ticks.
+struct Never;
+impl DelayNs for Never {
+    async fn delay_ns(&mut self, _ns: u32) {
+        core::future::pending::<()>().await;
+    }
+}
+
+/// A [`Dispatcher`] driven by async IO, with room for `TX_BUFFER` bytes of output before it is written out.
+pub struct AsyncDispatcher<'a, Context, const HISTORY: usize = 4, const OUTPUT: usize = 64, const DEPTH: usize = 8, const ROWS: usize = 0, const TX_BUFFER: usize = 1024> {
+    dispatcher: Dispatcher<'a, Context, HISTORY, OUTPUT, DEPTH, ROWS>,
+    input: [u8; 16],
+    output: [u8; TX_BUFFER],
+}
+impl<'a, Context, const HISTORY: usize, const OUTPUT: usize, const DEPTH: usize, const ROWS: usize, const TX_BUFFER: usize> AsyncDispatcher<'a, Context, HISTORY, OUTPUT, DEPTH, ROWS, TX_BUFFER> {
+    #[must_use]
+    pub const fn new(dispatcher: Dispatcher<'a, Context, HISTORY, OUTPUT, DEPTH, ROWS>) -> Self {
+        Self { dispatcher, input: [0; 16], output: [0; TX_BUFFER] }
+    }
+
+    /// Handle input until `io` fails.
+    ///
+    /// Nothing happens between key presses, so session and idle timeouts, watches and refreshes only catch up with
+    /// the next one. Use [`run_with_ticks`](Self::run_with_ticks) to keep those going.
+    ///
+    /// # Errors
+    ///
+    /// The first error of `io`.
+    #[allow(clippy::future_not_send)]
+    pub async fn run<T>(&mut self, ctx: &mut Context, io: &mut T) -> Result<(), T::Error>
+    where
+        T: Read + Write
+    {
+        self.run_with_ticks(ctx, io, &mut Never, 0).await
+    }
+
+    /// Handle input until `io` fails, waking up every `tick_ms` without input so timeouts, watches and refreshes
+    /// keep going.
+    ///
+    /// A read that is still waiting when the tick comes is dropped and started again, so `io` has to be cancel-safe:
+    /// dropping its read future must not lose any received bytes.
+    ///
+    /// # Errors
+    ///
+    /// The first error of `io`.
+    #[allow(clippy::future_not_send)]
+    pub async fn run_with_ticks<T, D>(&mut self, ctx: &mut Context, io: &mut T, delay: &mut D, tick_ms: u32) -> Result<(), T::Error>
+    where
+        T: Read + Write,
+        D: DelayNs
+    {
+        /* Received bytes not handed to the dispatcher yet are `input[start..end]` */
+        let (mut start, mut end) = (0, 0);
+        loop {
+            let mut port = Port { input: &self.input[start..end], output: &mut self.output, len: 0, full: false };
+            match self.dispatcher.poll(ctx, &mut port) {
+                Ok(()) | Err(nb::Error::WouldBlock) => {},
+                Err(nb::Error::Other(never)) => match never {},
+            }
+            let (len, full) = (port.len, port.full);
+            start = end - port.input.len();
+
+            io.write_all(&self.output[..len]).await?;
+            io.flush().await?;
+            if full || start < end {
+                /* Carry on with what did not fit */
+                continue;
+            }
+
+            let received = {
+                let mut read = pin!(io.read(&mut self.input));
+                let mut tick = pin!(delay.delay_ms(tick_ms));
+                poll_fn(|cx| match read.as_mut().poll(cx) {
+                    Poll::Ready(result) => Poll::Ready(Some(result)),
+                    Poll::Pending => tick.as_mut().poll(cx).map(|()| None),
+                }).await
+            };
+            start = 0;
+            end = received.unwrap_or(Ok(0))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AccessLevel, Idle, MenuItem, MenuItemType};
+    use core::{pin::Pin, sync::atomic::{AtomicU32, Ordering}, task::Context as TaskContext};
+    use std::{boxed::Box, collections::VecDeque, sync::Arc, task::{Wake, Waker}, vec::Vec};
+
+    struct Context {
+        value: u32,
+    }
+
+    static VALUE: MenuItem<'_, Context> = MenuItem {
+        name: "Value",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::WriteValue(|buf, ctx| {
+            let _ = write!(buf, "{}", ctx.value);
+        },
+        |buf, ctx| {
+            ctx.value = buf.parse()?;
+            Ok(())
+        }),
+    };
+
+    static MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Menu",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&VALUE], |_| true),
+    };
+
+    /// Hands out the input in the given chunks, then waits through `idle` reads and fails.
+    struct Chunks {
+        input: VecDeque<&'static [u8]>,
+        idle: usize,
+        output: Vec<u8>,
+        writes: usize,
+    }
+    impl Chunks {
+        fn new(input: Vec<&'static [u8]>, idle: usize) -> Self {
+            Self { input: VecDeque::from(input), idle, output: Vec::new(), writes: 0 }
+        }
+    }
+    impl embedded_io_async::ErrorType for Chunks {
+        type Error = embedded_io_async::ErrorKind;
+    }
+    impl Read for Chunks {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            if self.input.is_empty() && self.idle > 0 {
+                self.idle -= 1;
+                core::future::pending::<()>().await;
+            }
+            let chunk = self.input.pop_front().ok_or(embedded_io_async::ErrorKind::BrokenPipe)?;
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+    impl Write for Chunks {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.output.extend_from_slice(buf);
+            self.writes += 1;
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// Moves a clock on by the time waited, without waiting.
+    struct Ticks<'c> {
+        now: &'c AtomicU32,
+    }
+    impl DelayNs for Ticks<'_> {
+        async fn delay_ns(&mut self, ns: u32) {
+            let _ = self.now.fetch_add(ns / 1_000_000, Ordering::Relaxed);
+        }
+    }
+
+    struct NoopWaker;
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = TaskContext::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            if let Poll::Ready(output) = Pin::as_mut(&mut future).poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn chunked_io() {
+        let mut context = Context { value: 3 };
+        let mut io = Chunks::new(vec![&b"1"[..], b"4", b"2\r"], 0);
+
+        let mut runner = AsyncDispatcher::<_>::new(Dispatcher::new(&MENU));
+        let result = block_on(runner.run(&mut context, &mut io));
+
+        assert_eq!(result, Err(embedded_io_async::ErrorKind::BrokenPipe));
+        assert_eq!(context.value, 42);
+        assert_eq!(std::str::from_utf8(&io.output).unwrap(),
+                   "\r\nMenu\r\n [1] w=> Value: 3\r\nValue: 3\r\nEnter new value:\r\n42\r\n\r\nMenu\r\n [1] w=> Value: 42\r\n");
+        /* One write per chunk, including the initial menu */
+        assert_eq!(io.writes, 4);
+    }
+
+    #[test]
+    fn small_output() {
+        let mut context = Context { value: 3 };
+        let mut io = Chunks::new(vec![&b"1"[..]], 0);
+
+        /* Output that does not fit is written out in parts, none of it is lost */
+        let mut runner = AsyncDispatcher::<_, 4, 64, 8, 0, 8>::new(Dispatcher::new(&MENU));
+        let result = block_on(runner.run(&mut context, &mut io));
+
+        assert_eq!(result, Err(embedded_io_async::ErrorKind::BrokenPipe));
+        assert_eq!(std::str::from_utf8(&io.output).unwrap(), "\r\nMenu\r\n [1] w=> Value: 3\r\nValue: 3\r\nEnter new value:\r\n");
+        assert!(io.writes > 4);
+    }
+
+    #[test]
+    fn ticks() {
+        let mut context = Context { value: 3 };
+        let now = AtomicU32::new(0);
+        let clock = || now.load(Ordering::Relaxed);
+        let mut io = Chunks::new(vec![&b"1"[..], b"4"], 3);
+
+        /* The idle timeout fires while waiting on input */
+        let dispatcher = Dispatcher::new(&MENU).with_idle_timeout(Idle { clock: &clock, timeout_ms: 100, logout: false });
+        let mut runner = AsyncDispatcher::<_>::new(dispatcher);
+        let result = block_on(runner.run_with_ticks(&mut context, &mut io, &mut Ticks { now: &now }, 40));
+
+        assert_eq!(result, Err(embedded_io_async::ErrorKind::BrokenPipe));
+        assert_eq!(now.load(Ordering::Relaxed), 120);
+        assert_eq!(context.value, 3);
+        assert_eq!(std::str::from_utf8(&io.output).unwrap(),
+                   "\r\nMenu\r\n [1] w=> Value: 3\r\nValue: 3\r\nEnter new value:\r\n4\r\nInactivity timeout\r\n\r\nMenu\r\n [1] w=> Value: 3\r\n");
+    }
+}
@@ -0,0 +1,58 @@
+//! Line oriented command interface
+//!
+//! Commands address items by path, e.g. `get /Sub Menu 2/Uint`. A path starting with '/' is resolved from the root
+//! menu, any other path from the current menu.
+
+/// A single parsed command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command<'l> {
+    Get(&'l str),
+    Set(&'l str),
+    Exec(&'l str),
+    /// Keep reading a value, or the values in a menu.
+    Watch(&'l str),
+    Login,
+    Logout,
+}
+impl<'l> Command<'l> {
+    pub fn parse(line: &'l str) -> Option<Self> {
+        let line = line.trim();
+        let (cmd, path) = line.find(' ').map_or((line, ""), |i| (&line[..i], line[i + 1..].trim_start()));
+
+        match cmd {
+            "get" => Some(Command::Get(path)),
+            "set" => Some(Command::Set(path)),
+            "exec" => Some(Command::Exec(path)),
+            "watch" => Some(Command::Watch(path)),
+            "login" if path.is_empty() => Some(Command::Login),
+            "logout" if path.is_empty() => Some(Command::Logout),
+            _ => None,
+        }
+    }
+}
+
+/// Names of all known commands, used for completion.
+pub const COMMANDS: [&str; 6] = ["get", "set", "exec", "watch", "login", "logout"];
+
+/// The part of a command line that is subject to completion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion<'l> {
+    /// The command word itself.
+    Command(&'l str),
+    /// An item name, given the path of its parent menu and the part of the name typed so far.
+    Item(&'l str, &'l str),
+}
+impl<'l> Completion<'l> {
+    pub fn parse(line: &'l str) -> Self {
+        let line = line.trim_start();
+        let Some(i) = line.find(' ') else {
+            return Completion::Command(line);
+        };
+        let path = line[i + 1..].trim_start();
+        match path.rfind('/') {
+            Some(0) => Completion::Item("/", &path[1..]),
+            Some(j) => Completion::Item(&path[..j], &path[j + 1..]),
+            None => Completion::Item("", path),
+        }
+    }
+}
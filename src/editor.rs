@@ -0,0 +1,293 @@
+//! Line Editor
+//!
+//! Decodes key presses, including the ANSI escape sequences send by most terminals, and edits a line of input in
+//! place. The line is redrawn using only backspaces and spaces, so it works on even the most minimal terminals.
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+use arraydeque::{behavior::Saturating, ArrayDeque};
+
+use crate::{Transport, BACKSPACE, CARRIAGE_RETURN, DEL, ESCAPE, NEWLINE, TAB};
+
+const CTRL_A: char = '\x01';
+const CTRL_B: char = '\x02';
+const CTRL_E: char = '\x05';
+const CTRL_F: char = '\x06';
+const CTRL_U: char = '\x15';
+const CTRL_W: char = '\x17';
+
+/// A decoded key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    /// Delete everything before the cursor.
+    KillLine,
+    /// Delete the word before the cursor.
+    KillWord,
+    /// The terminal answered where its cursor is, counting from 1.
+    CursorReport { row: u16, column: u16 },
+}
+
+/// Progress through an escape sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    Esc,
+    /// Control Sequence Introducer, with the previous numeric parameter and the one so far.
+    Csi(u16, u16),
+    /// Single Shift 3, used by some terminals for the arrow keys.
+    Ss3,
+}
+
+pub struct KeyDecoder {
+    escape: Escape,
+}
+impl KeyDecoder {
+    pub const fn new() -> Self {
+        Self { escape: Escape::None }
+    }
+
+    /// Feed a received character, returns a key once a complete key press has been received.
+    pub fn decode(&mut self, c: char) -> Option<Key> {
+        let (escape, key) = match (self.escape, c) {
+            (Escape::None, ESCAPE) => (Escape::Esc, None),
+            (Escape::None, c) => (Escape::None, Some(match c {
+                                      NEWLINE | CARRIAGE_RETURN => Key::Enter,
+                                      TAB => Key::Tab,
+                                      BACKSPACE | DEL => Key::Backspace,
+                                      CTRL_A => Key::Home,
+                                      CTRL_B => Key::Left,
+                                      CTRL_E => Key::End,
+                                      CTRL_F => Key::Right,
+                                      CTRL_U => Key::KillLine,
+                                      CTRL_W => Key::KillWord,
+                                      c => Key::Char(c),
+                                  })),
+            (Escape::Esc, '[') => (Escape::Csi(0, 0), None),
+            (Escape::Csi(_, param), ';') => (Escape::Csi(param, 0), None),
+            (Escape::Esc, 'O') => (Escape::Ss3, None),
+            (Escape::Csi(previous, param), '0'..='9') => {
+                let digit = u16::from(c as u8 - b'0');
+                (Escape::Csi(previous, param.saturating_mul(10).saturating_add(digit)), None)
+            },
+            (Escape::Csi(previous, param), c) => (Escape::None, match (param, c) {
+                                                      (1 | 7, '~') => Some(Key::Home),
+                                                      (3, '~') => Some(Key::Delete),
+                                                      (4 | 8, '~') => Some(Key::End),
+                                                      (column, 'R') => Some(Key::CursorReport { row: previous, column }),
+                                                      (_, c) => Self::final_key(c),
+                                                  }),
+            (Escape::Ss3, c) => (Escape::None, Self::final_key(c)),
+            (Escape::Esc, _) => (Escape::None, None),
+        };
+
+        self.escape = escape;
+        key
+    }
+
+    const fn final_key(c: char) -> Option<Key> {
+        match c {
+            'A' => Some(Key::Up),
+            'B' => Some(Key::Down),
+            'C' => Some(Key::Right),
+            'D' => Some(Key::Left),
+            'H' => Some(Key::Home),
+            'F' => Some(Key::End),
+            _ => None,
+        }
+    }
+}
+
+/// A line of input with a cursor, every edit is echoed.
+pub struct LineEditor {
+    buffer: ArrayDeque<[char; 64], Saturating>,
+    cursor: usize,
+}
+impl LineEditor {
+    pub fn new() -> Self {
+        Self { buffer: ArrayDeque::new(), cursor: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.cursor == self.buffer.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &char> {
+        self.buffer.iter()
+    }
+
+    /// Forget the line without touching the terminal.
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.cursor = 0;
+    }
+
+    /// Forget the line after overwriting every character slot, so a secret does not linger in memory.
+    pub fn scrub(&mut self) {
+        /* Once full every slot is in use, the fence keeps the compiler from dropping these writes */
+        while self.buffer.push_back('\0').is_ok() {}
+        for c in self.buffer.iter_mut() {
+            *c = '\0';
+        }
+        compiler_fence(Ordering::SeqCst);
+        self.clear();
+    }
+
+    /// Append `c` without echoing it.
+    pub fn push(&mut self, c: char) {
+        if self.buffer.push_back(c).is_ok() {
+            self.cursor = self.buffer.len();
+        }
+    }
+
+    /// Remove the last character without touching the terminal.
+    pub fn pop(&mut self) -> Option<char> {
+        let c = self.buffer.pop_back();
+        self.cursor = self.buffer.len();
+        c
+    }
+
+    /// Apply an editing key. Returns false when `key` is not an editing key.
+    pub fn edit<S, E>(&mut self, key: Key, tx: &mut S) -> Result<bool, nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        match key {
+            Key::Char(c) if !c.is_control() => self.insert(c, tx)?,
+            Key::Backspace => self.delete_before(1, tx)?,
+            Key::Delete => {
+                if self.buffer.remove(self.cursor).is_some() {
+                    self.redraw_tail(1, tx)?;
+                }
+            },
+            Key::Left => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    tx.write(BACKSPACE as u8)?;
+                }
+            },
+            Key::Right => {
+                if let Some(c) = self.buffer.get(self.cursor) {
+                    tx.write(*c as u8)?;
+                    self.cursor += 1;
+                }
+            },
+            Key::Home => {
+                while self.cursor > 0 {
+                    self.cursor -= 1;
+                    tx.write(BACKSPACE as u8)?;
+                }
+            },
+            Key::End => {
+                while let Some(c) = self.buffer.get(self.cursor) {
+                    tx.write(*c as u8)?;
+                    self.cursor += 1;
+                }
+            },
+            Key::KillLine => self.delete_before(self.cursor, tx)?,
+            Key::KillWord => {
+                let before = self.buffer.iter().take(self.cursor);
+                let spaces = before.clone().rev().take_while(|c| c.is_whitespace()).count();
+                let word = before.rev().skip(spaces).take_while(|c| !c.is_whitespace()).count();
+                self.delete_before(spaces + word, tx)?;
+            },
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /// Insert `c` at the cursor.
+    pub fn insert<S, E>(&mut self, c: char, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        if self.buffer.insert(self.cursor, c).is_ok() {
+            tx.write(c as u8)?;
+            self.cursor += 1;
+            self.redraw_tail(0, tx)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the whole line with `line`, leaving the cursor at the end.
+    pub fn replace<S, E>(&mut self, line: &str, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let _ = self.edit(Key::End, tx)?;
+        self.delete_before(self.cursor, tx)?;
+        for c in line.chars() {
+            self.insert(c, tx)?;
+        }
+        Ok(())
+    }
+
+    /// Delete up to `n` characters before the cursor.
+    fn delete_before<S, E>(&mut self, n: usize, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let n = n.min(self.cursor);
+        for _ in 0..n {
+            self.cursor -= 1;
+            let _ = self.buffer.remove(self.cursor);
+            tx.write(BACKSPACE as u8)?;
+        }
+
+        if n > 0 {
+            self.redraw_tail(n, tx)?;
+        }
+        Ok(())
+    }
+
+    /// Redraw everything after the cursor, blanking `erased` stale characters past the end of the line.
+    fn redraw_tail<S, E>(&self, erased: usize, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let tail = self.buffer.len() - self.cursor;
+        for c in self.buffer.iter().skip(self.cursor) {
+            tx.write(*c as u8)?;
+        }
+        for _ in 0..erased {
+            tx.write(b' ')?;
+        }
+        for _ in 0..tail + erased {
+            tx.write(BACKSPACE as u8)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_keys() {
+        let mut keys = KeyDecoder::new();
+        let mut decode = |input: &str| input.chars().filter_map(|c| keys.decode(c)).collect::<std::vec::Vec<_>>();
+
+        assert_eq!(decode("a\r\x7f\x08\t"), [Key::Char('a'), Key::Enter, Key::Backspace, Key::Backspace, Key::Tab]);
+        assert_eq!(decode("\x1b[A\x1b[B\x1b[C\x1b[D"), [Key::Up, Key::Down, Key::Right, Key::Left]);
+        assert_eq!(decode("\x1bOH\x1b[F\x1b[1~\x1b[4~\x1b[3~"), [Key::Home, Key::End, Key::Home, Key::End, Key::Delete]);
+        assert_eq!(decode("\x01\x05\x15\x17"), [Key::Home, Key::End, Key::KillLine, Key::KillWord]);
+        assert_eq!(decode("\x1b[1;5C\x1b[Zb"), [Key::Right, Key::Char('b')]);
+        assert_eq!(decode("\x1b[24;80R"), [Key::CursorReport { row: 24, column: 80 }]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Input History
+//!
+//! A fixed capacity ring of previously entered lines, recalled with the up and down arrow keys.
+
+use heapless::{consts::U64, String};
+
+/// Kind of line kept in the history, values and commands are recalled separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Value,
+    Command,
+}
+
+struct Entry {
+    kind: Kind,
+    line: String<U64>,
+}
+
+/// Ring of the last `N` entered lines.
+pub struct History<const N: usize> {
+    entries: [Entry; N],
+    /// Index of the slot the next line is stored in.
+    head: usize,
+    len: usize,
+}
+impl<const N: usize> History<N> {
+    pub fn new() -> Self {
+        Self { entries: core::array::from_fn(|_| Entry { kind: Kind::Value, line: String::new() }), head: 0, len: 0 }
+    }
+
+    /// Store `line`, replacing the oldest entry when full. Empty lines and repeats of the previous line are skipped.
+    pub fn push(&mut self, kind: Kind, line: &str) {
+        if N == 0 || line.is_empty() || self.get(kind, 0) == Some(line) {
+            return;
+        }
+
+        let entry = &mut self.entries[self.head];
+        entry.kind = kind;
+        entry.line = String::new();
+        for c in line.chars() {
+            if entry.line.push(c).is_err() {
+                break;
+            }
+        }
+
+        self.head = (self.head + 1) % N;
+        self.len = N.min(self.len + 1);
+    }
+
+    /// The `age`th most recent line of `kind`, where 0 is the newest.
+    pub fn get(&self, kind: Kind, age: usize) -> Option<&str> {
+        (1..=self.len).map(|i| &self.entries[(self.head + N - i) % N])
+                      .filter(|e| e.kind == kind)
+                      .nth(age)
+                      .map(|e| e.line.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring() {
+        let mut history = History::<3>::new();
+        assert_eq!(history.get(Kind::Value, 0), None);
+
+        history.push(Kind::Value, "1");
+        history.push(Kind::Command, "get Uint");
+        history.push(Kind::Value, "2");
+        history.push(Kind::Value, "2");
+        history.push(Kind::Value, "");
+        assert_eq!(history.get(Kind::Value, 0), Some("2"));
+        assert_eq!(history.get(Kind::Value, 1), Some("1"));
+        assert_eq!(history.get(Kind::Command, 0), Some("get Uint"));
+
+        /* Overwrites the oldest entry */
+        history.push(Kind::Value, "3");
+        assert_eq!(history.get(Kind::Value, 0), Some("3"));
+        assert_eq!(history.get(Kind::Value, 1), Some("2"));
+        assert_eq!(history.get(Kind::Value, 2), None);
+        assert_eq!(history.get(Kind::Command, 0), Some("get Uint"));
+    }
+}
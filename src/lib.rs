@@ -34,27 +34,85 @@
 #[cfg(test)]
 #[macro_use] extern crate alloc;
 
-use core::fmt::Write;
-use arraydeque::{behavior::Wrapping, ArrayDeque};
-use embedded_hal::serial::{Read as HalRead, Write as HalWrite};
+use core::{
+    fmt::Write,
+    sync::atomic::{compiler_fence, Ordering},
+};
 use heapless::{
     consts::{
         U32, U64,
     },
-    String
+    String,
+    Vec,
 };
 
+mod access;
+#[cfg(feature = "async")]
+mod asynch;
+mod command;
+mod editor;
+mod history;
 mod macros;
+mod output;
+mod render;
+mod screen;
+mod stack;
+mod theme;
+mod transport;
+mod value;
+mod watch;
+
+pub use access::{AccessLevel, Clock, Idle, Login};
+#[cfg(feature = "embedded-io")]
+pub use transport::EmbeddedIo;
+pub use transport::{ByteSource, FmtTransport, Transport};
+#[cfg(feature = "async")]
+pub use asynch::AsyncDispatcher;
+use access::{Denied, Session};
+use output::{Buffered, Resume, Staging, Text};
+use render::View;
+use screen::{Fingerprint, Goto, Mode, Rewind, Screen};
+use stack::Stack;
+use watch::{Refresh, Watching};
+use command::{Command, Completion, COMMANDS};
+use editor::{Key, KeyDecoder, LineEditor};
+use history::{History, Kind as HistoryKind};
+pub use render::{Alternative, Compact, Entry, Kind, Prompt, Rejection, Renderer, Standard, Status};
+pub use theme::{Painted, Style, Theme};
+pub use value::{Choice, Flag, Invalid, Number, Numeric, Toggle, TypedValue};
+pub use watch::Watch;
 
 const DEL: char = '\x7f';
 const BACKSPACE: char = '\x08';
 const NEWLINE: char = '\n';
 const CARRIAGE_RETURN: char = '\r';
+const COMMAND: char = ':';
+const TAB: char = '\t';
+const ESCAPE: char = '\x1b';
+/// Room for a secret in bytes, what does not fit is not accepted.
+const SECRET_LEN: usize = 32;
 
 type Word = u8;
 
+/// Reasons for a callback to refuse a new value, these are reported to the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CallbackError {
     ParseError,
+    OutOfRange,
+    PermissionDenied,
+    Busy,
+    Custom(&'static str),
+}
+impl core::fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::ParseError => f.write_str("unable to parse"),
+            Self::OutOfRange => f.write_str("out of range"),
+            Self::PermissionDenied => f.write_str("permission denied"),
+            Self::Busy => f.write_str("device busy"),
+            Self::Custom(msg) => f.write_str(msg),
+        }
+    }
 }
 impl From<core::num::ParseIntError> for CallbackError {
     fn from(_error: core::num::ParseIntError) -> Self {
@@ -76,6 +134,31 @@ impl From<core::char::ParseCharError> for CallbackError {
         CallbackError::ParseError
     }
 }
+impl From<core::num::TryFromIntError> for CallbackError {
+    fn from(_error: core::num::TryFromIntError) -> Self {
+        Self::OutOfRange
+    }
+}
+
+/// How to confirm an action before it is carried out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confirm {
+    /// Act immediately.
+    Never,
+    /// Answer 'y' to a "y/N" question.
+    YesNo,
+    /// Type the name of the item, for actions which are hard to undo.
+    TypeName,
+}
+
+/// How to echo input that must stay secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mask {
+    /// Echo a '*' for every character.
+    Stars,
+    /// Echo nothing at all.
+    Hidden,
+}
 
 type ActiveCallbackFn<C> = fn(context: &C) -> bool;
 type ExecCallbackFn<C> = fn(context: &mut C);
@@ -87,54 +170,164 @@ pub enum MenuItemType<'a, C> {
     SubMenu(&'a [&'a MenuItem<'a, C>], ActiveCallbackFn<C>),
     ReadValue(ReadCallbackFn<C>),
     WriteValue(ReadCallbackFn<C>, WriteCallbackFn<C>),
-    ExecValue(ReadCallbackFn<C>, ExecCallbackFn<C>),
+    ExecValue(ReadCallbackFn<C>, ExecCallbackFn<C>, Confirm),
+    TypedValue(TypedValue<'a, C>),
+    /// Pick one of the choices from a numbered list.
+    Select(Choice<'a, C>),
+    /// A flag flipped by selecting it.
+    Toggle(Toggle<'a, C>),
+    /// A write only value of up to 32 bytes, entered masked and never shown.
+    Secret(WriteCallbackFn<C>, Mask),
 }
 
 pub struct MenuItem<'a, Context> {
     pub name: &'a str,
     pub hint: Option<&'a str>,
+    /// Level needed to use this item, and anything below it.
+    pub access: AccessLevel,
+    /// Optional, the dispatcher keeps track of the menus it went through. Only needed to go back from a menu that was
+    /// not reached by selecting it, such as the one the dispatcher starts in.
     pub parent: Option<&'a MenuItem<'a, Context>>,
     pub menu_type: MenuItemType<'a, Context>,
 }
 impl<'a, Context> MenuItem<'a, Context> {
-    fn value_to_string<S, E>(&self, ctx: &mut Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    /// Look up the item addressed by `path`, relative to this item.
+    ///
+    /// Path elements are item names separated by '/'; children of inactive sub menus are not visible.
+    pub fn find(&'a self, path: &str, ctx: &Context) -> Option<&'a Self> {
+        match self.resolve(path, AccessLevel::Factory, ctx) {
+            Some((item, "")) => Some(item),
+            _ => None,
+        }
+    }
+
+    /// Walk `path` down from this item. Anything following the addressed item after a space is returned as argument.
+    ///
+    /// Items requiring more than `level` are not visible.
+    fn resolve<'p>(&'a self, path: &'p str, level: AccessLevel, ctx: &Context) -> Option<(&'a Self, &'p str)> {
+        let mut item = self;
+        let mut rest = path;
+
+        while !rest.is_empty() {
+            let children = match item.menu_type {
+                MenuItemType::SubMenu(children, is_active) if is_active(ctx) => children,
+                _ => return None,
+            };
+
+            /* Names may contain spaces, so prefer the longest name that matches */
+            let (child, remainder) = children.iter()
+                                             .filter_map(|c| rest.strip_prefix(c.name).map(|r| (*c, r)))
+                                             .filter(|(c, r)| c.access <= level && (r.is_empty() || r.starts_with('/') || r.starts_with(' ')))
+                                             .max_by_key(|(c, _)| c.name.len())?;
+
+            item = child;
+            if let Some(arg) = remainder.strip_prefix(' ') {
+                return Some((item, arg.trim_start()));
+            }
+            rest = remainder.trim_start_matches('/');
+        }
+
+        Some((item, ""))
+    }
+
+    /// Write the current value into `buf`, returns false for items without a value.
+    fn read_value(&self, buf: &mut dyn Write, ctx: &Context) -> bool {
+        match self.menu_type {
+            MenuItemType::SubMenu(..) | MenuItemType::Secret(..) => return false,
+            MenuItemType::ReadValue(rcb) | MenuItemType::ExecValue(rcb, ..) | MenuItemType::WriteValue(rcb, ..) => rcb(buf, ctx),
+            MenuItemType::TypedValue(ref value) => value.read(buf, ctx),
+            MenuItemType::Select(ref choice) => { let _ = buf.write_str(choice.label(ctx)); },
+            MenuItemType::Toggle(ref toggle) => { let _ = buf.write_str(toggle.label((toggle.flag.get)(ctx))); },
+        }
+        true
+    }
+
+    /// Write the hint into `buf`, typed values without a hint describe their valid input instead.
+    fn hint_to_string(&self, buf: &mut dyn Write) -> bool {
+        match (self.hint, &self.menu_type) {
+            (Some(hint), _) => { let _ = buf.write_str(hint); },
+            (None, MenuItemType::TypedValue(value)) => value.hint(buf),
+            (None, MenuItemType::Select(choice)) => choice.hint(buf),
+            (None, MenuItemType::Toggle(toggle)) => toggle.hint(buf),
+            (None, _) => return false,
+        }
+        true
+    }
+
+    /// Explain why `input` was not accepted, as an error on the command line or as a notice in the menu.
+    fn reject<S, E>(&self, error: Invalid, input: &str, as_error: bool, view: View<'_>, tx: &mut S) -> Result<(), nb::Error<E> >
     where
-        S: HalWrite<Word, Error = E>
+        S: Transport<Error = E>
     {
-        let mut string = String::<U32>::new();
-        match self.menu_type {
-            MenuItemType::SubMenu(..) => (),
-            MenuItemType::ReadValue(ref rcb) | MenuItemType::ExecValue(ref rcb, ..) | MenuItemType::WriteValue(ref rcb, ..) => {
-                rcb(&mut string, ctx);
-                sprint!(tx, ": {}", string)?;
-            }
+        let mut hint = String::<U64>::new();
+        let has_hint = self.hint_to_string(&mut hint);
+        let rejection = Rejection { error, input, hint: if has_hint { Some(&hint) } else { None } };
+
+        if as_error {
+            view.error(tx, format_args!("{rejection}"))
+        } else {
+            view.notice(tx, format_args!("{rejection}"))
         }
+    }
 
+    /// Run the exec callback and show the resulting value.
+    fn exec<S, E>(&self, ctx: &mut Context, view: View<'_>, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        if let MenuItemType::ExecValue(readcb, execcb, _) = self.menu_type {
+            execcb(ctx);
+            let mut string = String::<U32>::new();
+            readcb(&mut string, ctx);
+            view.executed(tx, &string)?;
+        }
         Ok( () )
     }
 
-    fn menu_item_to_string<S, E>(&self, idx: usize, ctx: &mut Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    /// Flip a toggle, reporting when its callback refuses the new value.
+    fn flip<S, E>(&self, toggle: &Toggle<'a, Context>, ctx: &mut Context, view: View<'_>, tx: &mut S) -> Result<(), nb::Error<E> >
     where
-        S: HalWrite<Word, Error = E>
+        S: Transport<Error = E>
     {
-        match self.menu_type {
-            MenuItemType::SubMenu(_, is_active)    => {
-                sprint!(tx, " [{}] --> {}", idx, self.name)?;
-                if !is_active(ctx) { sprint!(tx, " (Disabled)")?; }
-            },
-            MenuItemType::ReadValue(..)  => { sprint!(tx, " [{:X}] r=> {}", idx, self.name)?; self.value_to_string(ctx, tx)?; },
-            MenuItemType::WriteValue(..) => { sprint!(tx, " [{:X}] w=> {}", idx, self.name)?; self.value_to_string(ctx, tx)?; },
-            MenuItemType::ExecValue(..)  => { sprint!(tx, " [{:X}] e=> {}", idx, self.name)?; self.value_to_string(ctx, tx)?; },
+        let value = !(toggle.flag.get)(ctx);
+        if let Err(error) = (toggle.flag.set)(ctx, value) {
+            self.reject(error.into(), toggle.label(value), false, view, tx)?;
         }
 
-        if let Some(hint) = self.hint {
-            sprint!(tx, " [{}]", hint)?;
+        Ok( () )
+    }
+
+    const fn kind(&self) -> Kind {
+        match self.menu_type {
+            MenuItemType::SubMenu(..) => Kind::SubMenu,
+            MenuItemType::ReadValue(..) => Kind::Read,
+            MenuItemType::WriteValue(..) | MenuItemType::TypedValue(..) | MenuItemType::Secret(..) => Kind::Write,
+            MenuItemType::ExecValue(..) => Kind::Exec,
+            MenuItemType::Select(..) => Kind::Select,
+            MenuItemType::Toggle(..) => Kind::Toggle,
         }
+    }
+}
 
-        sprintln!(tx)?;
+/// Overwrite `buf` before it goes out of scope, so a secret does not linger in memory.
+#[allow(deprecated)] // heapless 0.6 sizes its strings with the deprecated ArrayLength
+fn scrub<N: heapless::ArrayLength<u8>>(buf: &mut String<N>) {
+    /* Fill the whole capacity from the start, the fence keeps the compiler from dropping these writes */
+    *buf = String::new();
+    while buf.push('\0').is_ok() {}
+    compiler_fence(Ordering::SeqCst);
+    *buf = String::new();
+}
 
-        Ok( () )
+/// Number of hex digits needed to address `nr_children` items.
+const fn index_width(nr_children: usize) -> usize {
+    let mut width = 1;
+    let mut n = nr_children >> 4;
+    while n > 0 {
+        width += 1;
+        n >>= 4;
     }
+    width
 }
 
 #[allow(dead_code)]
@@ -144,14 +337,69 @@ enum MenuState {
     NeedIdx,
     NeedEnter,
     Processing,
+    NeedCommand,
+    Command,
+    NeedConfirm,
+    NeedName,
+    NeedPassword,
+    Login,
+    NeedSecret(Mask),
+}
+
+/// A line of a menu render, held in up to `N` bytes.
+enum Line<const N: usize> {
+    /// Not rendered yet.
+    Pending,
+    /// Rendered once, so a resumed write carries on with the same text even when the values in it changed since.
+    Buffered(Text<N>),
+    /// Too long to hold, rendered again when resumed and skipped up to where it stopped.
+    Direct,
+}
+
+/// How far a menu render got before the transmitter would block.
+struct Render<const N: usize> {
+    line: usize,
+    text: Line<N>,
+    /// Bytes of the line written so far.
+    byte: usize,
+    /// Of the lines completed so far.
+    print: Fingerprint,
+}
+impl<const N: usize> Render<N> {
+    const fn new(line: usize, print: Fingerprint) -> Self {
+        Self { line, text: Line::Pending, byte: 0, print }
+    }
 }
 
 #[allow(dead_code)]
-pub struct Dispatcher<'a, Context> {
+pub struct Dispatcher<'a, Context, const HISTORY: usize = 4, const OUTPUT: usize = 64, const DEPTH: usize = 8, const ROWS: usize = 0> {
     state: MenuState,
-    refresh_menu: bool,
+    refresh: Option<Refresh<'a>>,
+    /// Fingerprint of the last complete menu render in line mode.
+    printed: Option<u32>,
+    root: &'a MenuItem<'a, Context>,
     current_item: &'a MenuItem<'a, Context>,
-    buffer: ArrayDeque<[char; 32], Wrapping>,
+    /// The menus above the current item, innermost last. The outermost are forgotten beyond `DEPTH` levels.
+    stack: Stack<&'a MenuItem<'a, Context>, DEPTH>,
+    line: LineEditor,
+    keys: KeyDecoder,
+    last_tab: bool,
+    history: History<HISTORY>,
+    /// Age of the history entry currently recalled.
+    recall: Option<usize>,
+    login: Option<Login<'a, Context>>,
+    session: Session,
+    idle: Option<Idle<'a>>,
+    /// When the last input was received, for the idle timeout.
+    active_at: u32,
+    output: Staging<OUTPUT>,
+    /// Set while a menu render is in progress.
+    render: Option<Render<OUTPUT>>,
+    renderer: &'a dyn Renderer,
+    theme: &'a Theme,
+    screen: Screen<'a, Context, ROWS>,
+    watch: Option<Watch<'a>>,
+    watching: Option<Watching<'a, Context>>,
 }
 
 #[allow(dead_code)]
@@ -159,14 +407,215 @@ impl<'a, Context> Dispatcher<'a, Context> {
     pub fn new(main_menu: &'a MenuItem<'_, Context>) -> Self {
         Dispatcher {
             state: MenuState::Init,
-            refresh_menu: false,
+            refresh: None,
+            printed: None,
+            root: main_menu,
             current_item: main_menu,
-            buffer: ArrayDeque::new(),
+            stack: Stack::new(),
+            line: LineEditor::new(),
+            keys: KeyDecoder::new(),
+            last_tab: false,
+            history: History::new(),
+            recall: None,
+            login: None,
+            session: Session::new(),
+            idle: None,
+            active_at: 0,
+            output: Staging::new(),
+            render: None,
+            renderer: &Standard,
+            theme: &Theme::PLAIN,
+            screen: Screen::new(Mode::Off),
+            watch: None,
+            watching: None,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<'a, Context, const HISTORY: usize, const OUTPUT: usize, const DEPTH: usize, const ROWS: usize> Dispatcher<'a, Context, HISTORY, OUTPUT, DEPTH, ROWS> {
+    /// Keep the last `N` entered values and commands, instead of the default 4.
+    #[must_use]
+    pub fn with_history<const N: usize>(self) -> Dispatcher<'a, Context, N, OUTPUT, DEPTH, ROWS> {
+        Dispatcher {
+            state: self.state,
+            refresh: self.refresh,
+            printed: self.printed,
+            root: self.root,
+            current_item: self.current_item,
+            stack: self.stack,
+            line: self.line,
+            keys: self.keys,
+            last_tab: self.last_tab,
+            history: History::new(),
+            recall: None,
+            login: self.login,
+            session: self.session,
+            idle: self.idle,
+            active_at: self.active_at,
+            output: self.output,
+            render: self.render,
+            renderer: self.renderer,
+            theme: self.theme,
+            screen: self.screen,
+            watch: self.watch,
+            watching: self.watching,
+        }
+    }
+
+    /// Stage up to `N` bytes of output before writing them out, instead of the default 64.
+    ///
+    /// A menu render that stops because the transmitter would block carries on where it left off with the next step.
+    /// Lines of up to `N` bytes are written as they were first rendered, longer ones are rendered again and pick up
+    /// values that changed in between. Other responses, such as values, prompts and notices, are not resumed: when one
+    /// does not fit in the buffer while the transmitter would block, the rest of it is lost. Keep `N` above the longest
+    /// of those.
+    #[must_use]
+    pub fn with_output_buffer<const N: usize>(self) -> Dispatcher<'a, Context, HISTORY, N, DEPTH, ROWS> {
+        Dispatcher {
+            state: self.state,
+            refresh: self.refresh,
+            printed: self.printed,
+            root: self.root,
+            current_item: self.current_item,
+            stack: self.stack,
+            line: self.line,
+            keys: self.keys,
+            last_tab: self.last_tab,
+            history: self.history,
+            recall: self.recall,
+            login: self.login,
+            session: self.session,
+            idle: self.idle,
+            active_at: self.active_at,
+            output: Staging::new(),
+            render: None,
+            renderer: self.renderer,
+            theme: self.theme,
+            screen: self.screen,
+            watch: self.watch,
+            watching: self.watching,
         }
     }
 
-    pub const fn with_refresh(mut self) -> Self {
-        self.refresh_menu = true;
+    /// Remember the way back through up to `N` menus, instead of the default 8.
+    ///
+    /// Going back from a menu more than `N` levels down follows parent links past the menus that were forgotten, and
+    /// leads to the root when there are none. Keep `N` at the depth of the menu tree when submenus leave out their parent.
+    #[must_use]
+    pub fn with_depth<const N: usize>(self) -> Dispatcher<'a, Context, HISTORY, OUTPUT, N, ROWS> {
+        Dispatcher {
+            state: self.state,
+            refresh: self.refresh,
+            printed: self.printed,
+            root: self.root,
+            current_item: self.root,
+            stack: Stack::new(),
+            line: self.line,
+            keys: self.keys,
+            last_tab: self.last_tab,
+            history: self.history,
+            recall: self.recall,
+            login: self.login,
+            session: self.session,
+            idle: self.idle,
+            active_at: self.active_at,
+            output: self.output,
+            render: self.render,
+            renderer: self.renderer,
+            theme: self.theme,
+            screen: self.screen,
+            watch: self.watch,
+            watching: self.watching,
+        }
+    }
+
+    /// Draw the menu with `renderer`, instead of the [`Standard`] look.
+    #[must_use]
+    pub fn with_renderer(mut self, renderer: &'a dyn Renderer) -> Self {
+        self.renderer = renderer;
+        self
+    }
+
+    pub fn set_renderer(&mut self, renderer: &'a dyn Renderer) {
+        self.renderer = renderer;
+    }
+
+    /// Style the menu with `theme`, instead of plain text.
+    #[must_use]
+    pub const fn with_theme(mut self, theme: &'a Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Switch to `theme`, for instance to plain text once it turns out the terminal does not support ANSI codes.
+    pub const fn set_theme(&mut self, theme: &'a Theme) {
+        self.theme = theme;
+    }
+
+    /// Ask the terminal for its cursor position at start up, and switch to full screen mode once it answers.
+    ///
+    /// In full screen mode the menu is drawn in place, the arrow keys move the highlighted entry and enter selects it.
+    /// Terminals that do not answer stay in line mode. What the top `N` entries look like is remembered, so only those
+    /// that changed are redrawn. Entries further down are always redrawn, and with `N` at 0 there is no full screen mode.
+    #[must_use]
+    pub fn with_screen<const N: usize>(self) -> Dispatcher<'a, Context, HISTORY, OUTPUT, DEPTH, N> {
+        Dispatcher {
+            state: self.state,
+            refresh: self.refresh,
+            printed: self.printed,
+            root: self.root,
+            current_item: self.current_item,
+            stack: self.stack,
+            line: self.line,
+            keys: self.keys,
+            last_tab: self.last_tab,
+            history: self.history,
+            recall: self.recall,
+            login: self.login,
+            session: self.session,
+            idle: self.idle,
+            active_at: self.active_at,
+            output: self.output,
+            render: self.render,
+            renderer: self.renderer,
+            theme: self.theme,
+            screen: Screen::new(if N > 0 { Mode::Probe } else { Mode::Off }),
+            watch: self.watch,
+            watching: self.watching,
+        }
+    }
+
+    /// Allow values to be watched with the `watch` command, reading them again every `watch.interval_ms`.
+    #[must_use]
+    pub const fn with_watch(mut self, watch: Watch<'a>) -> Self {
+        self.watch = Some(watch);
+        self
+    }
+
+    /// Require a login for items above the user level. Without a login all items are accessible.
+    #[must_use]
+    pub fn with_login(mut self, login: Login<'a, Context>) -> Self {
+        self.session.touch(login.clock.now_ms());
+        self.login = Some(login);
+        self
+    }
+
+    /// Return to the root menu after `idle.timeout_ms` without input, discarding anything typed so far.
+    #[must_use]
+    pub fn with_idle_timeout(mut self, idle: Idle<'a>) -> Self {
+        self.active_at = idle.clock.now_ms();
+        self.idle = Some(idle);
+        self
+    }
+
+    /// Read the values in the menu again every `period_ms`, while waiting for a selection.
+    ///
+    /// In full screen mode changed values are redrawn in place, in line mode the menu is printed again when any of its
+    /// values changed.
+    #[must_use]
+    pub fn with_refresh(mut self, clock: &'a dyn Clock, period_ms: u32) -> Self {
+        self.refresh = Some(Refresh { clock, period_ms, since: clock.now_ms() });
         self
     }
 
@@ -175,438 +624,3413 @@ impl<'a, Context> Dispatcher<'a, Context> {
         self
     }
 
-    fn get_input<S, E>(&mut self, serial: &mut S) -> Result<(), nb::Error<E> >
-    where
-        S: HalRead<Word, Error = E> + HalWrite<Word, Error = E>
-    {
-        while self.state != MenuState::Processing {
-            let err = serial.read();
-            if let Ok(c) = err {
-                let c = c as char;
+    const fn view(&self) -> View<'a> {
+        View { renderer: self.renderer, theme: self.theme }
+    }
 
-                match self.state {
-                    MenuState::Init => {
-                        self.state = MenuState::NeedIdx;
-                    }
-                    MenuState::NeedIdx => {
-                        if c.is_ascii_hexdigit() {
-                            let _ = self.buffer.push_back(c);
-                            self.state = MenuState::Processing;
-                        } else if c == BACKSPACE || c == DEL {
-                            let _ = self.buffer.push_back('0');
-                            self.state = MenuState::Processing;
-                        } else if c == NEWLINE || c == CARRIAGE_RETURN {
-                            let _ = self.buffer.push_back(NEWLINE);
-                            return Ok(());
-                        } else {
-                            if c == 'x' {
-                                #[cfg(test)]
-                                return Err(nb::Error::WouldBlock);
-                            }
-                            continue;
-                        }
-                    }
-                    MenuState::NeedEnter => {
-                        if c == BACKSPACE || c == DEL {
-                            if self.buffer.pop_back().is_some() {
-                                serial.write(BACKSPACE as u8)?;
-                                serial.write(b' ')?;
-                                serial.write(BACKSPACE as u8)?;
-                                serial.flush()?;
-                            }
-                        } else if c == NEWLINE || c == CARRIAGE_RETURN {
-                            sprintln!(serial)?;
-                            if !self.buffer.is_empty() {
-                                self.state = MenuState::Processing;
-                            }
-                        } else {
-                            let _ = self.buffer.push_back(c);
-                            serial.write(c as u8)?;
-                            serial.flush()?;
-                            serial.flush()?;
-                        }
-                    }
-                    MenuState::Processing => {}
-                }
-            } else if let Err(e) = err {
-                return Err(e);
+    /// The access level of the current session.
+    const fn level(&self) -> AccessLevel {
+        match self.login {
+            Some(_) => self.session.level(),
+            None => AccessLevel::Factory,
+        }
+    }
+
+    /// True when the current item and every menu above it are accessible.
+    fn is_accessible(&self) -> bool {
+        let mut above = self.stack.first().map_or(self.current_item.parent, |outer| outer.parent);
+        while let Some(item) = above {
+            if item.access > self.level() {
+                return false;
             }
+            above = item.parent;
+        }
+        self.stack.iter().chain(core::iter::once(self.current_item)).all(|item| item.access <= self.level())
+    }
+
+    /// The menu above the current item.
+    fn parent(&self) -> Option<&'a MenuItem<'a, Context>> {
+        match (self.stack.last(), self.current_item.parent) {
+            (Some(parent), _) | (None, Some(parent)) => Some(parent),
+            (None, None) if core::ptr::eq(self.current_item, self.root) => None,
+            (None, None) => Some(self.root),
         }
+    }
 
-        Ok(())
+    /// Make `item` the current item, remembering the way back.
+    fn enter(&mut self, item: &'a MenuItem<'a, Context>) {
+        self.stack.push(self.current_item);
+        self.current_item = item;
     }
 
-    fn display_menu<S, E>(&self, ctx: &mut Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    /// Go back to the menu above the current item.
+    fn leave(&mut self) {
+        if let Some(parent) = self.parent() {
+            let _ = self.stack.pop();
+            self.current_item = parent;
+        }
+    }
+
+    /// Go back to the root menu.
+    fn home(&mut self) {
+        self.stack.clear();
+        self.current_item = self.root;
+    }
+
+    /// Ask for a password, the line is processed once enter is pressed.
+    fn ask_password<S, E>(&mut self, tx: &mut S) -> Result<(), nb::Error<E> >
     where
-        S: HalWrite<Word, Error = E>
+        S: Transport<Error = E>
     {
-        sprintln!(tx)?;
-        sprintln!(tx, "{}", self.current_item.name)?;
+        self.line.clear();
+        self.state = MenuState::NeedPassword;
+        self.view().prompt(tx, Prompt::Password)?;
+        tx.flush()
+    }
 
-        if let MenuItemType::SubMenu(children, is_active) = self.current_item.menu_type {
-            if let Some(parent) = self.current_item.parent {
-                sprintln!(tx, " <0> <-- Back to {}", parent.name)?;
-            }
+    fn process_login<S, E>(&mut self, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let mut password = self.buffer_line();
+        self.line.scrub();
+        self.state = MenuState::NeedIdx;
 
-            if is_active(ctx) {
-                for (i,c) in children.iter().enumerate() {
-                    c.menu_item_to_string(i+1, ctx, tx)?;
-                }
-            }
+        let result = match self.login {
+            Some(ref login) => self.session.login(login, &password, ctx, login.clock.now_ms()),
+            None => return self.view().error(tx, format_args!("no login required")),
+        };
+        scrub(&mut password);
+
+        let view = self.view();
+        match result {
+            Ok(level) => view.notice(tx, format_args!("Logged in with {level} access"))?,
+            Err(Denied::Password) => view.notice(tx, format_args!("Access denied"))?,
+            Err(Denied::LockedOut) => view.notice(tx, format_args!("Too many attempts, try again later"))?,
         }
 
-        Ok(())
+        self.display_menu(ctx, tx)
     }
 
-    pub fn run<S, E>(&mut self, ctx: &mut Context, serial: &mut S) -> Result<(), nb::Error<E> >
+    /// Drop back to the user level after a period of inactivity, leaving any menu that is no longer accessible.
+    fn check_session<S, E>(&mut self, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
     where
-        S: HalRead<Word, Error = E> + HalWrite<Word, Error = E>
+        S: Transport<Error = E>
     {
-        if self.state == MenuState::Init {
-            self.display_menu(ctx, serial)?;
-            self.state = MenuState::NeedIdx;
+        let expired = match self.login {
+            Some(ref login) => self.session.expire(login, login.clock.now_ms()),
+            None => false,
+        };
+        if !expired {
+            return Ok(());
         }
 
-        let mut result = Ok(());
+        if !self.is_accessible() {
+            self.home();
+        }
+        self.line.clear();
+        self.state = MenuState::NeedIdx;
 
-        while result.is_ok() {
-            let mut changed = false;
+        sprintln!(tx)?;
+        self.view().notice(tx, format_args!("Session expired"))?;
+        self.display_menu(ctx, tx)
+    }
 
-            self.get_input(serial)?;
+    /// Go back to the root menu when idle, unless already there with nothing pending.
+    fn check_idle<S, E>(&mut self, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let (now, logout) = match self.idle {
+            Some(ref idle) => {
+                let now = idle.clock.now_ms();
+                if !idle.is_idle(self.active_at, now) {
+                    return Ok(());
+                }
+                (now, idle.logout && self.session.level() != AccessLevel::User)
+            },
+            None => return Ok(()),
+        };
+        self.active_at = now;
 
-            if let Some(input) = self.buffer.front() {
-                let current_idx = if let Some(digit) = input.to_digit(16) {
-                    digit as usize
-                } else if *input == NEWLINE {
-                    let _ = self.buffer.pop_front();
-                    self.display_menu(ctx, serial)?;
-                    continue;
-                } else {
-                    let _ = self.buffer.pop_front();
-                    continue;
-                };
+        let home = core::ptr::eq(self.current_item, self.root) && self.state == MenuState::NeedIdx && self.line.is_empty();
+        if home && !logout && self.watching.is_none() {
+            return Ok(());
+        }
 
-                result = if current_idx == 0 {
-                    self.state = MenuState::NeedIdx;
-                    if let Some(parent) = self.current_item.parent {
-                        let _ = self.buffer.pop_front();
-                        self.current_item = parent;
-                        changed = true;
-                        Ok(())
-                    } else {
-                        let _ = self.buffer.pop_front();
-                        changed = true;
-                        Ok( () )
-                    }
-                } else if let MenuItemType::SubMenu(menu, _is_active) = self.current_item.menu_type {
-                    self.state = MenuState::NeedIdx;
-                    let _ = self.buffer.pop_front();
-
-                    if let Some(child) = menu.get(current_idx - 1) {
-                        match child.menu_type {
-                            MenuItemType::SubMenu(_, is_active) => {
-                                if is_active(ctx) {
-                                    self.current_item = child;
-                                    changed = true;
-                                }
-                                else {
-                                    sprintln!(serial, "{} is disabled.", child.name)?;
-                                }
-                                Ok(())
-                            }
-                            MenuItemType::ReadValue(rcb) => {
-                                let mut buffer = String::<U32>::new();
-                                rcb(&mut buffer, ctx);
-                                sprintln!(serial, "{}: {}", child.name, buffer)
-                            }
-                            MenuItemType::WriteValue(rcb, _) => {
-                                self.state = MenuState::NeedEnter;
-                                self.current_item = child;
-
-                                let mut buffer = String::<U32>::new();
-                                rcb(&mut buffer, ctx);
-                                sprintln!(serial, "{}: {}", child.name, buffer)?;
-                                match child.hint {
-                                    None => sprintln!(serial, "Enter new value:"),
-                                    Some(h) => sprintln!(serial, "Enter new value: [{}]", h),
-                                }
-                            }
-                            MenuItemType::ExecValue(readcb, execcb) => {
-                                execcb(ctx);
-                                let mut string = String::<U32>::new();
-                                readcb(&mut string, ctx);
-                                sprintln!(serial, "> {}", string)?;
-                                //changed = true;
-                                Ok( () )
-                            }
-                        }
-                    } else { Ok( () ) }
-                } else if let MenuItemType::WriteValue(_rcb, wcb) = self.current_item.menu_type {
-                    let mut buffer = String::<U32>::new();
+        if logout {
+            self.session.logout();
+        }
+        self.line.scrub();
+        self.recall = None;
+        self.watching = None;
+        self.home();
+        self.state = MenuState::NeedIdx;
 
-                    /* Set menu state after writing */
-                    self.state = MenuState::NeedIdx;
-                    self.current_item = self.current_item.parent.expect("Item has no parent?");
-                    changed = true;
+        if self.screen.is_on() {
+            /* Redrawing clears the screen, the notice goes below the menu */
+            self.screen.drawn = None;
+            self.display_menu(ctx, tx)?;
+            return self.view().notice(tx, format_args!("Inactivity timeout"));
+        }
 
-                    for i in 0..buffer.capacity() {
-                        if let Some(c) = self.buffer.pop_front() {
-                            if i < buffer.capacity() {
-                                buffer.push(c).expect("cannot push into buffer?!?");
-                            }
-                        }
-                        else { break; }
-                    }
+        sprintln!(tx)?;
+        self.view().notice(tx, format_args!("Inactivity timeout"))?;
+        self.display_menu(ctx, tx)
+    }
 
-                    /* Clean input buffer if some joker put a lot in it. */
-                    while let Some(_) = self.buffer.pop_front() {}
+    const fn nr_children(&self) -> usize {
+        match self.current_item.menu_type {
+            MenuItemType::SubMenu(children, _) => children.len(),
+            MenuItemType::Select(ref choice) => choice.choices.len(),
+            _ => 0,
+        }
+    }
 
-                    if wcb(&buffer, ctx).is_err() {
-                        sprintln!(serial, "Unable to parse {}", buffer)?;
-                    }
+    /// Index typed so far, together with the number of digits it consists of.
+    fn pending_idx(&self) -> (usize, usize) {
+        self.line.iter().filter_map(|c| c.to_digit(16)).fold((0, 0), |(idx, digits), d| (idx * 16 + d as usize, digits + 1))
+    }
 
-                    Ok( () )
-                } else {
-                    unimplemented!();
-                };
-            };
+    /// The line currently in the input buffer.
+    fn buffer_line(&self) -> String<U64> {
+        let mut line = String::<U64>::new();
+        for c in self.line.iter() {
+            let _ = line.push(*c);
+        }
+        line
+    }
 
-            if changed {
-                self.display_menu(ctx, serial)?;
+    /// Replace the line being edited with an older (or newer) line from the history.
+    fn recall<S, E>(&mut self, older: bool, serial: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let kind = match self.state {
+            MenuState::NeedEnter => HistoryKind::Value,
+            MenuState::NeedCommand => HistoryKind::Command,
+            _ => return Ok(()),
+        };
+        let age = match (self.recall, older) {
+            (None, true) => Some(0),
+            (Some(age), true) => Some(age + 1),
+            (None | Some(0), false) => None,
+            (Some(age), false) => Some(age - 1),
+        };
+        if let Some(age) = age {
+            if self.history.get(kind, age).is_none() {
+                return Ok(());
             }
         }
-        result
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::*;
-    use embedded_hal_mock as mock;
-    use mock::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+        let history = &self.history;
+        self.line.replace(age.and_then(|age| history.get(kind, age)).unwrap_or(""), serial)?;
 
-    struct Context {
-        bool_value: bool,
-        uint_value: u32,
+        self.recall = age;
+        Ok(())
     }
 
-    static MAIN_MENU: MenuItem<'_, Context> = MenuItem {
-        name: "Main",
-        hint: None,
-        parent: None,
-        menu_type: MenuItemType::SubMenu(&[&SUB1, &SUB2], |_| true)
-    };
+    /// Complete the command or item name at the end of the command line.
+    ///
+    /// Completes as far as the candidates agree, a second tab lists them all.
+    fn complete<S, E>(&mut self, ctx: &Context, serial: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let line = self.buffer_line();
 
-    static BOOL_VAL: MenuItem<'_, Context> = MenuItem {
-        name: "Bool",
-        hint: Some("boolean"),
-        parent: None,
-        menu_type: MenuItemType::ReadValue(|buf, ctx| { let _ = write!(buf, "{}", ctx.bool_value); } ),
-    };
+        let mut candidates = Vec::<(&str, char), U32>::new();
+        let typed = match Completion::parse(&line) {
+            Completion::Command(prefix) => {
+                for name in COMMANDS.iter().filter(|name| name.starts_with(prefix)) {
+                    let _ = candidates.push((name, ' '));
+                }
+                prefix.len()
+            },
+            Completion::Item(path, prefix) => {
+                let (start, path) = match path.strip_prefix('/') {
+                    Some(path) => (self.root, path),
+                    None => (self.current_item, path),
+                };
 
-    static UINT_VAL: MenuItem<'_, Context> = MenuItem {
-        name: "Uint",
-        hint: None,
-        parent: None,
-        menu_type: MenuItemType::ReadValue(|buf, ctx| { let _ = write!(buf, "{}", ctx.uint_value); } ),
-    };
+                if let Some((menu, "")) = start.resolve(path, self.level(), ctx) {
+                    if let MenuItemType::SubMenu(children, is_active) = menu.menu_type {
+                        for child in children.iter().filter(|c| is_active(ctx) && c.access <= self.level() && c.name.starts_with(prefix)) {
+                            let suffix = if let MenuItemType::SubMenu(..) = child.menu_type { '/' } else { ' ' };
+                            let _ = candidates.push((child.name, suffix));
+                        }
+                    }
+                }
+                prefix.len()
+            },
+        };
+
+        /* Complete up to where the candidates start to differ */
+        let mut completion = "";
+        let mut suffix = None;
+        if let Some(((first, first_suffix), others)) = candidates.split_first() {
+            completion = &first[typed..];
+            if others.is_empty() {
+                suffix = Some(*first_suffix);
+            }
 
-    static UINT_VAL_WRITE: MenuItem<'_, Context> = MenuItem {
-        name: "Uint_Write",
-        hint: None,
-        parent: Some(&SUB2),
-        menu_type: MenuItemType::WriteValue(|buf, ctx| {
-            let _ = write!(buf, "{}", ctx.uint_value);
-        },
-        |buf, ctx| {
-            let i: u32 = buf.parse()?;
-            ctx.uint_value = i;
-            Ok( () )
-        }),
-    };
+            for (name, _) in others {
+                let common = completion.char_indices()
+                                       .zip(name[typed..].chars())
+                                       .take_while(|((_, a), b)| a == b)
+                                       .last()
+                                       .map_or(0, |((i, a), _)| i + a.len_utf8());
+                completion = &completion[..common];
+            }
+        }
 
-    static SUB1: MenuItem<'_, Context> = MenuItem {
-        name: "Sub Menu 1",
-        hint: None,
-        parent: Some(&MAIN_MENU),
-        menu_type: MenuItemType::SubMenu(&[&BOOL_VAL], |_| true)
-    };
+        if !completion.is_empty() || suffix.is_some() {
+            for c in completion.chars().chain(suffix) {
+                self.line.insert(c, serial)?;
+            }
+        } else if candidates.len() > 1 && self.last_tab {
+            sprintln!(serial)?;
+            for (name, suffix) in &candidates {
+                sprint!(serial, "{}{}  ", name, if *suffix == '/' { "/" } else { "" })?;
+            }
+            sprintln!(serial)?;
+            sprint!(serial, "{}", COMMAND)?;
+            sprint!(serial, "{}", line)?;
+        }
 
-    static SUB2: MenuItem<'_, Context> = MenuItem {
-        name: "Sub Menu 2",
-        hint: None,
-        parent: Some(&MAIN_MENU),
-        menu_type: MenuItemType::SubMenu(&[&UINT_VAL, &UINT_VAL_WRITE], |_| true)
-    };
+        Ok(())
+    }
+
+    /// Ask `question` about `item` and how to confirm it, the answer is processed with `item` as the current item.
+    fn ask_confirmation<S, E>(&mut self, item: &'a MenuItem<'a, Context>, question: core::fmt::Arguments<'_>, confirm: Confirm, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        self.state = match confirm {
+            Confirm::Never => return Ok(()),
+            Confirm::YesNo => MenuState::NeedConfirm,
+            Confirm::TypeName => MenuState::NeedName,
+        };
+        self.view().prompt(tx, Prompt::Confirm { question, confirm, name: item.name })?;
+        self.enter(item);
+        tx.flush()
+    }
+
+    /// Whether the answer in the line buffer confirms the current item.
+    fn is_confirmed(&self, confirm: Confirm) -> bool {
+        match confirm {
+            Confirm::Never => true,
+            Confirm::YesNo => self.buffer_line().eq_ignore_ascii_case("y"),
+            Confirm::TypeName => self.buffer_line() == self.current_item.name,
+        }
+    }
+
+    /// Edit a line without ever echoing it, returns true once enter is pressed.
+    fn edit_secret<S, E>(&mut self, key: Key, mask: Mask, room: usize, tx: &mut S) -> Result<bool, nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        match key {
+            Key::Enter => {
+                sprintln!(tx)?;
+                return Ok(true);
+            },
+            Key::Backspace => {
+                let erased = self.line.pop().is_some();
+                if erased && mask == Mask::Stars {
+                    tx.write(BACKSPACE as u8)?;
+                    tx.write(b' ')?;
+                    tx.write(BACKSPACE as u8)?;
+                    tx.flush()?;
+                }
+            },
+            Key::Char(c) if !c.is_control() => {
+                /* A secret cut short would be stored wrong, so refuse what does not fit */
+                let len: usize = self.line.iter().map(|c| c.len_utf8()).sum();
+                if len + c.len_utf8() > room {
+                    return Ok(false);
+                }
+                self.line.push(c);
+                if mask == Mask::Stars {
+                    tx.write(b'*')?;
+                    tx.flush()?;
+                }
+            },
+            _ => {},
+        }
+        Ok(false)
+    }
+
+    fn get_input<S, E>(&mut self, ctx: &Context, serial: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        while !matches!(self.state, MenuState::Processing | MenuState::Command | MenuState::Login) {
+            self.check_session(ctx, serial)?;
+            self.check_idle(ctx, serial)?;
+
+            let err = serial.read();
+            if let Ok(c) = err {
+                if let Some(ref login) = self.login {
+                    self.session.touch(login.clock.now_ms());
+                }
+                if let Some(ref idle) = self.idle {
+                    self.active_at = idle.clock.now_ms();
+                }
+
+                let Some(key) = self.keys.decode(c as char) else {
+                    continue;
+                };
+
+                if let Key::CursorReport { row, .. } = key {
+                    /* The terminal answered with its size, take over the screen */
+                    if self.screen.mode == Mode::Probe {
+                        self.screen.mode = Mode::On;
+                        self.screen.height = usize::from(row);
+                        self.screen.drawn = None;
+                        if self.state == MenuState::NeedIdx && self.line.is_empty() {
+                            return Ok(());
+                        }
+                    }
+                    continue;
+                }
+
+                if self.watching.take().is_some() {
+                    /* Any key stops watching, and brings back the menu */
+                    return Ok(());
+                }
+
+                match self.state {
+                    MenuState::Init => {
+                        self.state = MenuState::NeedIdx;
+                    }
+                    MenuState::NeedIdx => {
+                        if self.menu_key(key, ctx, serial)? {
+                            return Ok(());
+                        }
+                    },
+                    MenuState::NeedEnter => match key {
+                        Key::Enter => {
+                            sprintln!(serial)?;
+                            if !self.line.is_empty() {
+                                self.history.push(HistoryKind::Value, &self.buffer_line());
+                                self.recall = None;
+                                self.state = MenuState::Processing;
+                            }
+                        },
+                        Key::Up | Key::Down => {
+                            self.recall(key == Key::Up, serial)?;
+                            serial.flush()?;
+                        },
+                        _ => {
+                            let _ = self.line.edit(key, serial)?;
+                            serial.flush()?;
+                        },
+                    },
+                    MenuState::NeedCommand => self.command_key(key, ctx, serial)?,
+                    MenuState::NeedConfirm => {
+                        /* A single key answers, anything but 'y' declines */
+                        if let Key::Char(c) = key {
+                            if !c.is_control() {
+                                serial.write(c as u8)?;
+                                self.line.push(c);
+                            }
+                        }
+                        sprintln!(serial)?;
+                        self.state = MenuState::Processing;
+                    },
+                    MenuState::NeedName => {
+                        if key == Key::Enter {
+                            sprintln!(serial)?;
+                            self.state = MenuState::Processing;
+                        } else {
+                            let _ = self.line.edit(key, serial)?;
+                            serial.flush()?;
+                        }
+                    },
+                    MenuState::NeedPassword => {
+                        if self.edit_secret(key, Mask::Stars, usize::MAX, serial)? {
+                            self.state = MenuState::Login;
+                        }
+                    },
+                    MenuState::NeedSecret(mask) => {
+                        if self.edit_secret(key, mask, SECRET_LEN, serial)? && !self.line.is_empty() {
+                            self.state = MenuState::Processing;
+                        }
+                    },
+                    MenuState::Processing | MenuState::Command | MenuState::Login => {}
+                }
+
+                self.last_tab = key == Key::Tab;
+            } else if let Err(e) = err {
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle `key` while waiting for a selection. Returns true when the menu is to be shown again.
+    fn menu_key<S, E>(&mut self, key: Key, ctx: &Context, serial: &mut S) -> Result<bool, nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        match key {
+            Key::Char(c) if c.is_ascii_hexdigit() => {
+                let nr_children = self.nr_children();
+                self.line.push(c);
+
+                /* Commit as soon as no further digit can result in a valid index. */
+                let (idx, digits) = self.pending_idx();
+                if idx == 0 || idx * 16 > nr_children || digits >= index_width(nr_children) {
+                    if digits > 1 {
+                        sprintln!(serial, "{}", c)?;
+                    }
+                    self.state = MenuState::Processing;
+                } else {
+                    serial.write(c as u8)?;
+                    serial.flush()?;
+                }
+            },
+            Key::Backspace => {
+                if self.line.pop().is_some() {
+                    serial.write(BACKSPACE as u8)?;
+                    serial.write(b' ')?;
+                    serial.write(BACKSPACE as u8)?;
+                    serial.flush()?;
+                } else {
+                    self.line.push('0');
+                    self.state = MenuState::Processing;
+                }
+            },
+            Key::Up | Key::Down if self.screen.drawn.is_some() && self.line.is_empty() => {
+                let screen = &mut self.screen;
+                let selected = match key {
+                    Key::Up if screen.selected > screen.lowest => screen.selected - 1,
+                    Key::Down if screen.selected < screen.highest => screen.selected + 1,
+                    _ => screen.selected,
+                };
+                if selected != screen.selected {
+                    /* Every row is redrawn when the menu scrolls */
+                    let _ = screen.select(selected);
+                }
+                self.update_screen(ctx, serial)?;
+                serial.flush()?;
+            },
+            Key::Enter if self.screen.drawn.is_some() && self.line.is_empty() => {
+                let selected = self.screen.selected;
+                for digit in (0..index_width(selected)).rev() {
+                    self.line.push(char::from(b"0123456789abcdef"[(selected >> (digit * 4)) & 0xf]));
+                }
+                self.state = MenuState::Processing;
+            },
+            Key::Enter => {
+                if self.line.is_empty() {
+                    return Ok(true);
+                }
+                sprintln!(serial)?;
+                self.state = MenuState::Processing;
+            },
+            Key::Char(COMMAND) if self.line.is_empty() => {
+                serial.write(COMMAND as u8)?;
+                serial.flush()?;
+                self.state = MenuState::NeedCommand;
+            },
+            #[cfg(test)]
+            Key::Char('x') => return Err(nb::Error::WouldBlock),
+            _ => {},
+        }
+        Ok(false)
+    }
+
+    /// Handle `key` while a command line is being typed.
+    fn command_key<S, E>(&mut self, key: Key, ctx: &Context, serial: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        match key {
+            Key::Enter => {
+                sprintln!(serial)?;
+                if !self.addresses_secret(ctx) {
+                    self.history.push(HistoryKind::Command, &self.buffer_line());
+                }
+                self.recall = None;
+                self.state = if self.line.is_empty() { MenuState::NeedIdx } else { MenuState::Command };
+            },
+            Key::Tab => {
+                if self.line.at_end() {
+                    self.complete(ctx, serial)?;
+                }
+                serial.flush()?;
+            },
+            Key::Up | Key::Down => {
+                self.recall(key == Key::Up, serial)?;
+                serial.flush()?;
+            },
+            _ => {
+                let _ = self.line.edit(key, serial)?;
+                serial.flush()?;
+            },
+        }
+        Ok(())
+    }
+
+    fn display_menu<S, E>(&mut self, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        if self.screen.is_on() {
+            let last = self.last_entry(ctx);
+            let drawn = matches!(self.screen.drawn, Some(item) if core::ptr::eq(item, self.current_item));
+            if drawn && last == self.screen.highest {
+                return self.update_screen(ctx, tx);
+            }
+
+            let mut header = Fingerprint::new();
+            let _ = self.view().header(&mut header, self.current_item.name);
+            let lowest = usize::from(self.parent().is_none());
+            self.screen.layout(header.lines, lowest, last);
+            self.screen.selected = if last > 0 { 1 } else { lowest };
+            self.screen.drawn = Some(self.current_item);
+        }
+
+        self.render = Some(Render::new(0, Fingerprint::new()));
+        self.resume_render(ctx, tx)
+    }
+
+    /// Continue the menu render in progress, from where the transmitter would block.
+    fn resume_render<S, E>(&mut self, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        while let Some(mut render) = self.render.take() {
+            if matches!(render.text, Line::Pending) {
+                let mut buffer = Text::new();
+                render.text = match self.render_line(render.line, ctx, &mut buffer) {
+                    Ok(true) => Line::Buffered(buffer),
+                    Ok(false) => {
+                        self.printed = Some(render.print.hash);
+                        continue;
+                    },
+                    Err(_) => Line::Direct,
+                };
+            }
+
+            let result = match render.text {
+                Line::Buffered(ref text) => {
+                    let result = output::write_from(tx, text.as_bytes(), &mut render.byte);
+                    if result.is_ok() {
+                        let _ = render.print.write_all(text.as_bytes());
+                    }
+                    result
+                },
+                Line::Pending | Line::Direct => {
+                    let mut out = Resume::new(tx, render.byte, render.print);
+                    let result = self.render_line(render.line, ctx, &mut out).map(|_| ());
+                    render.byte = out.position;
+                    if result.is_ok() {
+                        render.print = out.print;
+                    }
+                    result
+                },
+            };
+
+            match result {
+                Ok(()) => self.render = Some(Render::new(render.line + 1, render.print)),
+                Err(nb::Error::WouldBlock) => {
+                    self.render = Some(render);
+                    return Err(nb::Error::WouldBlock);
+                },
+                Err(e) => {
+                    self.screen.drawn = None;
+                    return Err(e);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Index of the last entry of the current menu, 0 when it has none.
+    fn last_entry(&self, ctx: &Context) -> usize {
+        match self.current_item.menu_type {
+            MenuItemType::SubMenu(children, is_active) if is_active(ctx) => children.len(),
+            MenuItemType::Select(ref choice) => choice.choices.len(),
+            _ => 0,
+        }
+    }
+
+    /// Print line `line` of the menu, returns false once past the last one.
+    fn render_line<S, E>(&mut self, line: usize, ctx: &Context, tx: &mut S) -> Result<bool, nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        match line {
+            0 => {
+                if self.screen.is_on() {
+                    sprint!(tx, "{}", screen::CLEAR)?;
+                }
+                self.view().header(tx, self.current_item.name)?;
+            },
+            _ if self.screen.is_on() && self.screen.first + line - 1 <= self.screen.last => {
+                self.write_row(self.screen.first + line - 1, false, ctx, tx)?;
+            },
+            _ if !self.screen.is_on() && line - 1 <= self.last_entry(ctx) => self.write_row(line - 1, false, ctx, tx)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Print `item` as entry `index` of the menu.
+    fn draw_entry<S, E>(&self, item: &MenuItem<'a, Context>, index: usize, width: usize, highlighted: bool, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let mut value = String::<U32>::new();
+        let mut hint = String::<U64>::new();
+
+        /* Locked items do not give away their value */
+        let status = match item.menu_type {
+            _ if item.access > self.level() => Status::Locked,
+            MenuItemType::SubMenu(_, is_active) if !is_active(ctx) => Status::Disabled,
+            _ if item.read_value(&mut value, ctx) => Status::Available(Some(&value)),
+            _ => Status::Available(None),
+        };
+        let has_hint = status != Status::Locked && item.hint_to_string(&mut hint);
+
+        self.view().entry(tx, &Entry {
+            index,
+            width,
+            kind: item.kind(),
+            name: item.name,
+            status,
+            hint: if has_hint { Some(&hint) } else { None },
+            highlighted,
+        })
+    }
+
+    /// Print entry `idx` of the menu, 0 being the way back to the parent.
+    fn draw_row<S, E>(&self, idx: usize, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let width = index_width(self.nr_children());
+        let highlighted = self.screen.is_on() && idx == self.screen.selected;
+        let view = self.view();
+
+        match (idx.checked_sub(1), &self.current_item.menu_type) {
+            (None, _) => self.parent().map_or(Ok(()), |parent| view.back(tx, width, parent.name, highlighted)),
+            (Some(i), MenuItemType::SubMenu(children, _)) => {
+                children.get(i).map_or(Ok(()), |c| self.draw_entry(c, idx, width, highlighted, ctx, tx))
+            },
+            (Some(i), MenuItemType::Select(choice)) => choice.choices.get(i).map_or(Ok(()), |label| {
+                view.choice(tx, &Alternative { index: idx, width, label, current: i == (choice.get)(ctx), highlighted })
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Print entry `idx`, in full screen mode remembering what it looks like. With `in_place` it is only printed when
+    /// it changed since, on its own row.
+    fn write_row<S, E>(&mut self, idx: usize, in_place: bool, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        if !self.screen.is_on() {
+            return self.draw_row(idx, ctx, tx);
+        }
+
+        /* Drawn twice rather than kept, first only to see what it looks like */
+        let mut print = Fingerprint::new();
+        let _ = self.draw_row(idx, ctx, &mut print);
+        if in_place {
+            if self.screen.cached(idx).as_deref() == Some(&Some(print.hash)) {
+                return Ok(());
+            }
+            sprint!(tx, "{}{}", Goto(self.screen.row(idx)), screen::CLEAR_LINE)?;
+        }
+
+        self.draw_row(idx, ctx, tx)?;
+        if let Some(row) = self.screen.cached(idx) {
+            *row = Some(print.hash);
+        }
+        Ok(())
+    }
+
+    /// Redraw the entries of the menu on screen that changed, leaving the cursor where it is.
+    fn update_screen<S, E>(&mut self, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        sprint!(tx, "{}", screen::SAVE_CURSOR)?;
+        for idx in self.screen.first..=self.screen.last {
+            self.write_row(idx, true, ctx, tx)?;
+        }
+        sprint!(tx, "{}", screen::RESTORE_CURSOR)
+    }
+
+    /// In full screen mode draw the menu now rather than after a message, which would otherwise be cleared with the
+    /// screen.
+    fn redraw_first<S, E>(&mut self, changed: &mut bool, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        if *changed && self.screen.is_on() {
+            *changed = false;
+            self.display_menu(ctx, tx)?;
+        }
+        Ok(())
+    }
+
+    /// Look up `path`, from the root when it starts with a `/` and from the current menu otherwise.
+    fn lookup<'p>(&self, path: &'p str, ctx: &Context) -> Option<(&'a MenuItem<'a, Context>, &'p str)> {
+        let (start, path) = path.strip_prefix('/').map_or((self.current_item, path), |path| (self.root, path));
+        start.resolve(path, self.level(), ctx)
+    }
+
+    /// Whether the command line addresses a secret, such a line is never kept in the history.
+    fn addresses_secret(&self, ctx: &Context) -> bool {
+        let mut line = self.buffer_line();
+        let secret = match Command::parse(&line) {
+            Some(Command::Get(path) | Command::Set(path) | Command::Exec(path) | Command::Watch(path)) => {
+                matches!(self.lookup(path, ctx), Some((item, _)) if matches!(item.menu_type, MenuItemType::Secret(..)))
+            },
+            _ => false,
+        };
+        scrub(&mut line);
+        secret
+    }
+
+    fn process_command<S, E>(&mut self, ctx: &mut Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        /* The line might hold a secret, do not leave it behind */
+        let mut line = self.buffer_line();
+        self.line.scrub();
+        self.state = MenuState::NeedIdx;
+
+        let result = self.run_command(&line, ctx, tx);
+        scrub(&mut line);
+        result
+    }
+
+    fn run_command<S, E>(&mut self, line: &str, ctx: &mut Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let Some(command) = Command::parse(line) else {
+            return self.view().error(tx, format_args!("unknown command"));
+        };
+
+        let path = match command {
+            Command::Get(path) | Command::Set(path) | Command::Exec(path) | Command::Watch(path) => path,
+            Command::Login => return self.ask_password(tx),
+            Command::Logout => {
+                self.session.logout();
+                if !self.is_accessible() {
+                    self.home();
+                }
+                return self.view().notice(tx, format_args!("Logged out"));
+            },
+        };
+        let Some((item, arg)) = self.lookup(path, ctx) else {
+            return self.view().error(tx, format_args!("no such item"));
+        };
+
+        match (command, &item.menu_type) {
+            (Command::Set(_), MenuItemType::WriteValue(_, wcb)) => {
+                let mut buffer = String::<U32>::new();
+                let result = if buffer.push_str(arg).is_ok() { wcb(&buffer, ctx) } else { Err(CallbackError::ParseError) };
+                if let Err(error) = result {
+                    return item.reject(error.into(), arg, true, self.view(), tx);
+                }
+            },
+            (Command::Set(_), MenuItemType::TypedValue(value)) => {
+                if let Err(error) = value.write(arg, ctx) {
+                    return item.reject(error, arg, true, self.view(), tx);
+                }
+            },
+            (Command::Set(_), MenuItemType::Select(choice)) => {
+                if let Err(error) = choice.write(arg, ctx) {
+                    return item.reject(error, arg, true, self.view(), tx);
+                }
+            },
+            (Command::Set(_), MenuItemType::Toggle(toggle)) if toggle.confirm != Confirm::Never => {
+                /* Guarded flags ask first, just like from the menu */
+                match toggle.parse(arg) {
+                    Ok(value) if value != (toggle.flag.get)(ctx) => {
+                        return self.ask_confirmation(item, format_args!("Set {} to {}?", item.name, toggle.label(value)), toggle.confirm, tx);
+                    },
+                    Ok(_) => {},
+                    Err(error) => return item.reject(error, arg, true, self.view(), tx),
+                }
+            },
+            (Command::Set(_), MenuItemType::Toggle(toggle)) => {
+                if let Err(error) = toggle.write(arg, ctx) {
+                    return item.reject(error, arg, true, self.view(), tx);
+                }
+            },
+            /* The command line is echoed while typing */
+            (Command::Set(_), MenuItemType::Secret(..)) => return self.view().error(tx, format_args!("{} can only be set from the menu", item.name)),
+            (Command::Set(_), _) => return self.view().error(tx, format_args!("{} is not writable", item.name)),
+            (_, _) if !arg.is_empty() => return self.view().error(tx, format_args!("unexpected argument {arg}")),
+            (Command::Watch(_), _) => return self.start_watch(item, ctx, tx),
+            (Command::Exec(_), MenuItemType::ExecValue(_, _, confirm)) if *confirm != Confirm::Never => {
+                return self.ask_confirmation(item, format_args!("Are you sure?"), *confirm, tx);
+            },
+            (Command::Exec(_), MenuItemType::ExecValue(_, execcb, _)) => execcb(ctx),
+            (Command::Exec(_), MenuItemType::Toggle(toggle)) if toggle.confirm != Confirm::Never => {
+                let value = !(toggle.flag.get)(ctx);
+                return self.ask_confirmation(item, format_args!("Set {} to {}?", item.name, toggle.label(value)), toggle.confirm, tx);
+            },
+            (Command::Exec(_), MenuItemType::Toggle(toggle)) => {
+                let value = !(toggle.flag.get)(ctx);
+                if let Err(error) = (toggle.flag.set)(ctx, value) {
+                    return item.reject(error.into(), toggle.label(value), true, self.view(), tx);
+                }
+            },
+            (Command::Get(_), MenuItemType::SubMenu(..)) => return self.view().error(tx, format_args!("{} is a menu", item.name)),
+            (Command::Get(_), MenuItemType::Secret(..)) => return self.view().error(tx, format_args!("{} is write only", item.name)),
+            (Command::Get(_) | Command::Login | Command::Logout, _) => {},
+            (Command::Exec(_), _) => return self.view().error(tx, format_args!("{} is not executable", item.name)),
+        }
+
+        let mut value = String::<U32>::new();
+        let _ = item.read_value(&mut value, ctx);
+        self.view().value(tx, item.name, &value)
+    }
+
+    fn start_watch<S, E>(&mut self, item: &'a MenuItem<'a, Context>, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let now = match self.watch {
+            Some(ref watch) => watch.clock.now_ms(),
+            None => return self.view().error(tx, format_args!("watching is not enabled")),
+        };
+
+        if let MenuItemType::SubMenu(_, is_active) = item.menu_type {
+            if !is_active(ctx) {
+                return self.view().error(tx, format_args!("{} is disabled", item.name));
+            }
+        }
+
+        /* Count the values first, so there is no notice when there is nothing to watch */
+        if self.draw_watch(item, ctx, &mut Fingerprint::new()).unwrap_or(0) == 0 {
+            return self.view().error(tx, format_args!("{} has no values", item.name));
+        }
+        self.view().notice(tx, format_args!("Watching {}, press any key to stop", item.name))?;
+        let lines = self.draw_watch(item, ctx, tx)?;
+        self.watching = Some(Watching { item, since: now, lines });
+        Ok(())
+    }
+
+    /// Read the watched values again when they are due, and print them over the previous ones.
+    fn update_watch<S, E>(&mut self, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let (item, lines, now) = match (&self.watch, &self.watching) {
+            (Some(watch), Some(watching)) => {
+                let now = watch.clock.now_ms();
+                if !watching.is_due(watch, now) {
+                    return Ok(());
+                }
+                (watching.item, watching.lines, now)
+            },
+            _ => return Ok(()),
+        };
+
+        sprint!(tx, "{}", Rewind(lines))?;
+        let lines = self.draw_watch(item, ctx, tx)?;
+        self.watching = Some(Watching { item, since: now, lines });
+        Ok(())
+    }
+
+    /// Refresh the menu when it is due, unless the user is in the middle of something.
+    fn refresh_menu<S, E>(&mut self, ctx: &Context, tx: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let due = match self.refresh {
+            Some(ref mut refresh) => refresh.is_due(refresh.clock.now_ms()),
+            None => false,
+        };
+        if !due || self.state != MenuState::NeedIdx || !self.line.is_empty() || self.watching.is_some() {
+            return Ok(());
+        }
+
+        if !self.screen.is_on() {
+            /* Only print the menu again when it looks different */
+            let mut print = Fingerprint::new();
+            let mut line = 0;
+            while self.render_line(line, ctx, &mut print) == Ok(true) {
+                line += 1;
+            }
+            if self.printed == Some(print.hash) {
+                return Ok(());
+            }
+        }
+        self.display_menu(ctx, tx)
+    }
+
+    /// Print the value of `item`, or the values in it when it is a menu. Returns the number of values printed.
+    fn draw_watch<S, E>(&self, item: &MenuItem<'a, Context>, ctx: &Context, tx: &mut S) -> Result<usize, nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        /* A disabled menu hides its values */
+        let items = match item.menu_type {
+            MenuItemType::SubMenu(_, is_active) if !is_active(ctx) => &[],
+            MenuItemType::SubMenu(children, _) => children,
+            _ => core::slice::from_ref(&item),
+        };
+
+        let mut lines = 0;
+        for item in items.iter().filter(|item| item.access <= self.level()) {
+            let mut value = String::<U32>::new();
+            if item.read_value(&mut value, ctx) {
+                sprint!(tx, "{}", screen::CLEAR_LINE)?;
+                self.view().value(tx, item.name, &value)?;
+                lines += 1;
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Handle a single input: a selection, an entered value, a command or a password.
+    ///
+    /// Only the bytes needed are read from `serial`. When no input is available `WouldBlock` is returned right away;
+    /// everything received so far is kept, so the next call resumes where this one left off.
+    ///
+    /// Output is written in bulk and flushed once the input has been handled.
+    ///
+    /// # Errors
+    ///
+    /// `WouldBlock` when no input is available, or the first other error of `serial`.
+    pub fn step<S, E>(&mut self, ctx: &mut Context, serial: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        let mut staging = core::mem::replace(&mut self.output, Staging::new());
+        let mut output = Buffered::new(serial, &mut staging);
+        let result = self.handle(ctx, &mut output);
+        let finished = output.finish();
+        self.output = staging;
+
+        finished?;
+        result
+    }
+
+    /// Act on `child` being selected from the current menu.
+    fn open_entry<S, E>(&mut self, child: &'a MenuItem<'a, Context>, changed: &mut bool, ctx: &mut Context, serial: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        match child.menu_type {
+            MenuItemType::SubMenu(_, is_active) => {
+                if is_active(ctx) {
+                    self.enter(child);
+                    *changed = true;
+                }
+                else {
+                    self.view().notice(serial, format_args!("{} is disabled.", child.name))?;
+                }
+                Ok(())
+            }
+            MenuItemType::ReadValue(rcb) => {
+                let mut buffer = String::<U32>::new();
+                rcb(&mut buffer, ctx);
+                self.view().value(serial, child.name, &buffer)
+            }
+            MenuItemType::WriteValue(..) | MenuItemType::TypedValue(..) => {
+                self.state = MenuState::NeedEnter;
+                self.enter(child);
+
+                let mut buffer = String::<U32>::new();
+                let _ = child.read_value(&mut buffer, ctx);
+                self.view().value(serial, child.name, &buffer)?;
+
+                let mut hint = String::<U64>::new();
+                let has_hint = child.hint_to_string(&mut hint);
+                self.view().prompt(serial, Prompt::Value { hint: if has_hint { Some(&hint) } else { None } })
+            }
+            MenuItemType::ExecValue(.., confirm) => {
+                if confirm == Confirm::Never {
+                    child.exec(ctx, self.view(), serial)?;
+                } else {
+                    self.ask_confirmation(child, format_args!("Are you sure?"), confirm, serial)?;
+                }
+                //changed = true;
+                Ok( () )
+            }
+            MenuItemType::Select(..) => {
+                self.enter(child);
+                *changed = true;
+                Ok( () )
+            }
+            MenuItemType::Secret(_, mask) => {
+                self.state = MenuState::NeedSecret(mask);
+                self.enter(child);
+
+                let mut hint = String::<U64>::new();
+                let has_hint = child.hint_to_string(&mut hint);
+                self.view().prompt(serial, Prompt::Secret { name: child.name, hint: if has_hint { Some(&hint) } else { None } })?;
+                serial.flush()
+            }
+            MenuItemType::Toggle(ref toggle) => {
+                if toggle.confirm == Confirm::Never {
+                    child.flip(toggle, ctx, self.view(), serial)?;
+                    *changed = true;
+                } else {
+                    let value = !(toggle.flag.get)(ctx);
+                    self.ask_confirmation(child, format_args!("Set {} to {}?", child.name, toggle.label(value)), toggle.confirm, serial)?;
+                }
+                Ok( () )
+            }
+        }
+    }
+
+    /// Write the value entered for the current item.
+    fn write_entry<S, E>(&mut self, changed: &mut bool, ctx: &mut Context, serial: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        match self.current_item.menu_type {
+            MenuItemType::WriteValue(_rcb, wcb) => {
+                let item = self.current_item;
+                let mut buffer = String::<U32>::new();
+
+                /* Set menu state after writing */
+                self.state = MenuState::NeedIdx;
+                self.leave();
+                *changed = true;
+
+                for c in self.line.iter() {
+                    if buffer.push(*c).is_err() {
+                        break;
+                    }
+                }
+
+                /* Clean input buffer if some joker put a lot in it. */
+                self.line.clear();
+
+                if let Err(error) = wcb(&buffer, ctx) {
+                    self.redraw_first(changed, ctx, serial)?;
+                    item.reject(error.into(), &buffer, false, self.view(), serial)?;
+                }
+
+                Ok( () )
+            }
+            MenuItemType::Secret(wcb, _) => {
+                let item = self.current_item;
+                /* Holds SECRET_LEN bytes */
+                let mut buffer = String::<U32>::new();
+
+                self.state = MenuState::NeedIdx;
+                self.leave();
+                *changed = true;
+
+                for c in self.line.iter() {
+                    if buffer.push(*c).is_err() {
+                        break;
+                    }
+                }
+                self.line.scrub();
+
+                let result = wcb(&buffer, ctx);
+                scrub(&mut buffer);
+
+                /* Never repeat the input */
+                if let Err(error) = result {
+                    self.redraw_first(changed, ctx, serial)?;
+                    item.reject(error.into(), "value", false, self.view(), serial)?;
+                }
+
+                Ok( () )
+            }
+            MenuItemType::TypedValue(ref value) => {
+                let item = self.current_item;
+                let input = self.buffer_line();
+                self.line.clear();
+
+                /* Set menu state after writing */
+                self.state = MenuState::NeedIdx;
+                self.leave();
+                *changed = true;
+
+                if let Err(error) = value.write(&input, ctx) {
+                    self.redraw_first(changed, ctx, serial)?;
+                    item.reject(error, &input, false, self.view(), serial)?;
+                }
+
+                Ok( () )
+            }
+            _ => {
+                /* Read only values are never entered, should one be anyway go back rather than get stuck */
+                self.line.clear();
+                self.state = MenuState::NeedIdx;
+                self.leave();
+                *changed = true;
+                Ok( () )
+            }
+        }
+    }
+
+    fn handle<S, E>(&mut self, ctx: &mut Context, serial: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        if self.render.is_some() {
+            self.resume_render(ctx, serial)?;
+        }
+
+        if self.state == MenuState::Init {
+            self.state = MenuState::NeedIdx;
+            if self.screen.mode == Mode::Probe {
+                sprint!(serial, "{}", screen::QUERY)?;
+            }
+            self.display_menu(ctx, serial)?;
+        }
+
+        self.update_watch(ctx, serial)?;
+        self.refresh_menu(ctx, serial)?;
+
+        let mut changed = false;
+
+        self.get_input(ctx, serial)?;
+
+        if self.state == MenuState::Command {
+            return self.process_command(ctx, serial);
+        }
+
+        if self.state == MenuState::Login {
+            return self.process_login(ctx, serial);
+        }
+
+        if self.state != MenuState::Processing {
+            /* Enter without a selection, redraw the menu */
+            self.line.clear();
+            return self.display_menu(ctx, serial);
+        }
+
+        if self.screen.drawn.is_some() && matches!(self.current_item.menu_type, MenuItemType::SubMenu(..) | MenuItemType::Select(..)) {
+            /* Make room below the menu for what the selection prints */
+            sprint!(serial, "{}{}", Goto(self.screen.status_row()), screen::CLEAR_BELOW)?;
+        }
+
+        let result = match self.current_item.menu_type {
+            MenuItemType::SubMenu(menu, _is_active) => {
+                let (current_idx, _) = self.pending_idx();
+                self.line.clear();
+                self.state = MenuState::NeedIdx;
+
+                if current_idx == 0 {
+                    self.leave();
+                    changed = true;
+                    Ok( () )
+                } else if let Some(child) = menu.get(current_idx - 1).filter(|c| c.access > self.level()) {
+                    self.view().notice(serial, format_args!("{} requires {} access", child.name, child.access))?;
+                    self.ask_password(serial)
+                } else if let Some(child) = menu.get(current_idx - 1) {
+                    self.open_entry(child, &mut changed, ctx, serial)
+                } else { Ok( () ) }
+            }
+            MenuItemType::Select(ref choice) => {
+                let item = self.current_item;
+                let (current_idx, _) = self.pending_idx();
+                self.line.clear();
+                self.state = MenuState::NeedIdx;
+
+                let label = current_idx.checked_sub(1).and_then(|i| choice.choices.get(i));
+                if label.is_none() && current_idx != 0 {
+                    return Ok( () );
+                }
+
+                /* Back to the menu the choice was made from */
+                self.leave();
+                changed = true;
+
+                if let Some(label) = label {
+                    if let Err(error) = (choice.set)(ctx, current_idx - 1) {
+                        self.redraw_first(&mut changed, ctx, serial)?;
+                        item.reject(error.into(), label, false, self.view(), serial)?;
+                    }
+                }
+                Ok( () )
+            }
+            MenuItemType::ExecValue(.., confirm) => {
+                let item = self.current_item;
+                let confirmed = self.is_confirmed(confirm);
+                self.line.clear();
+                self.state = MenuState::NeedIdx;
+                self.leave();
+
+                if confirmed {
+                    item.exec(ctx, self.view(), serial)?;
+                } else {
+                    self.view().notice(serial, format_args!("Cancelled"))?;
+                }
+                Ok( () )
+            }
+            MenuItemType::Toggle(ref toggle) => {
+                let item = self.current_item;
+                let confirmed = self.is_confirmed(toggle.confirm);
+                self.line.clear();
+                self.state = MenuState::NeedIdx;
+                self.leave();
+                changed = true;
+
+                if confirmed {
+                    item.flip(toggle, ctx, self.view(), serial)?;
+                }
+                Ok( () )
+            }
+            MenuItemType::WriteValue(..) | MenuItemType::Secret(..) | MenuItemType::TypedValue(..) | MenuItemType::ReadValue(_) => {
+                self.write_entry(&mut changed, ctx, serial)
+            },
+        };
+
+        if changed {
+            self.display_menu(ctx, serial)?;
+        }
+        result
+    }
+
+    /// Handle all input that is available without blocking, for use from a main loop alongside other tasks.
+    ///
+    /// # Errors
+    ///
+    /// `WouldBlock` once the available input has been consumed, or the first other error of `serial`.
+    pub fn poll<S, E>(&mut self, ctx: &mut Context, serial: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        loop {
+            self.step(ctx, serial)?;
+        }
+    }
+
+    /// Handle input until `serial` returns an error, see [`poll`](Self::poll).
+    ///
+    /// # Errors
+    ///
+    /// The first error of `serial`, including `WouldBlock`.
+    pub fn run<S, E>(&mut self, ctx: &mut Context, serial: &mut S) -> Result<(), nb::Error<E> >
+    where
+        S: Transport<Error = E>
+    {
+        self.poll(ctx, serial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use alloc::vec::Vec;
+    use embedded_hal_mock as mock;
+    use mock::eh0::serial::{Mock as SerialMock, Transaction as SerialTransaction};
+
+    struct Context {
+        bool_value: bool,
+        uint_value: u32,
+    }
+
+    /// Flushes the mock at the end of every line and of every partial line that is made visible, so the expected
+    /// output can be written line by line regardless of how it is buffered.
+    struct Lines<'m> {
+        serial: &'m mut SerialMock<u8>,
+        partial: bool,
+    }
+    impl<'m> Lines<'m> {
+        fn new(serial: &'m mut SerialMock<u8>) -> Self {
+            Self { serial, partial: false }
+        }
+    }
+    impl Transport for Lines<'_> {
+        type Error = mock::eh0::MockError;
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            self.serial.read()
+        }
+
+        fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+            self.serial.write(word)?;
+            self.partial = word != b'\n';
+            if self.partial {
+                Ok( () )
+            } else {
+                self.serial.flush()
+            }
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            if !self.partial {
+                return Ok( () );
+            }
+            self.partial = false;
+            self.serial.flush()
+        }
+    }
+
+    static MAIN_MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Main",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&SUB1, &SUB2], |_| true)
+    };
+
+    static BOOL_VAL: MenuItem<'_, Context> = MenuItem {
+        name: "Bool",
+        hint: Some("boolean"),
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::ReadValue(|buf, ctx| { let _ = write!(buf, "{}", ctx.bool_value); } ),
+    };
+
+    static UINT_VAL: MenuItem<'_, Context> = MenuItem {
+        name: "Uint",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::ReadValue(|buf, ctx| { let _ = write!(buf, "{}", ctx.uint_value); } ),
+    };
+
+    static UINT_VAL_WRITE: MenuItem<'_, Context> = MenuItem {
+        name: "Uint_Write",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::WriteValue(|buf, ctx| {
+            let _ = write!(buf, "{}", ctx.uint_value);
+        },
+        |buf, ctx| {
+            let i: u32 = buf.parse()?;
+            ctx.uint_value = i;
+            Ok( () )
+        }),
+    };
+
+    static SUB1: MenuItem<'_, Context> = MenuItem {
+        name: "Sub Menu 1",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&MAIN_MENU),
+        menu_type: MenuItemType::SubMenu(&[&BOOL_VAL], |_| true)
+    };
+
+    static SUB2: MenuItem<'_, Context> = MenuItem {
+        name: "Sub Menu 2",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&MAIN_MENU),
+        menu_type: MenuItemType::SubMenu(&[&UINT_VAL, &UINT_VAL_WRITE], |_| true)
+    };
+
+    static LARGE_MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Large",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&UINT_VAL; 17], |_| true)
+    };
+
+    static DUTY: MenuItem<'_, Context> = MenuItem {
+        name: "Duty",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&TYPED_MENU),
+        menu_type: MenuItemType::TypedValue(TypedValue::Uint(Number {
+            min: 0,
+            max: 100,
+            step: 5,
+            unit: Some("%"),
+            get: |ctx| ctx.uint_value,
+            set: |ctx, value| {
+                ctx.uint_value = value;
+                Ok( () )
+            },
+        })),
+    };
+
+    static TYPED_MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Typed",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&DUTY], |_| true)
+    };
+
+    static GUARDED: MenuItem<'_, Context> = MenuItem {
+        name: "Guarded",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&GUARDED_MENU),
+        menu_type: MenuItemType::WriteValue(|buf, ctx| {
+            let _ = write!(buf, "{}", ctx.uint_value);
+        },
+        |buf, ctx| {
+            let i: u32 = buf.parse()?;
+            match i {
+                13 => Err(CallbackError::Custom("unlucky number")),
+                _ if i > 100 => Err(CallbackError::OutOfRange),
+                _ if !ctx.bool_value => Err(CallbackError::PermissionDenied),
+                _ => Err(CallbackError::Busy),
+            }
+        }),
+    };
+
+    static GUARDED_MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Guarded Menu",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&GUARDED], |_| true)
+    };
+
+    static MODE: MenuItem<'_, Context> = MenuItem {
+        name: "Mode",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&CHOICE_MENU),
+        menu_type: MenuItemType::Select(Choice {
+            choices: &["Slow", "Medium", "Fast"],
+            get: |ctx| ctx.uint_value as usize,
+            set: |ctx, index| {
+                if index == 2 && !ctx.bool_value {
+                    return Err(CallbackError::PermissionDenied);
+                }
+                ctx.uint_value = core::convert::TryFrom::try_from(index)?;
+                Ok(())
+            },
+        }),
+    };
+
+    static CHOICE_MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Choice Menu",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&MODE], |_| true)
+    };
+
+    static POWER: MenuItem<'_, Context> = MenuItem {
+        name: "Power",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&TOGGLE_MENU),
+        menu_type: MenuItemType::Toggle(Toggle {
+            labels: None,
+            confirm: Confirm::Never,
+            flag: Flag {
+                get: |ctx| ctx.bool_value,
+                set: |ctx, value| {
+                    ctx.bool_value = value;
+                    Ok(())
+                },
+            },
+        }),
+    };
+
+    static RELAY: MenuItem<'_, Context> = MenuItem {
+        name: "Relay",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&TOGGLE_MENU),
+        menu_type: MenuItemType::Toggle(Toggle {
+            labels: Some(["open", "closed"]),
+            confirm: Confirm::YesNo,
+            flag: Flag {
+                get: |ctx| ctx.uint_value != 0,
+                set: |ctx, value| {
+                    ctx.uint_value = value.into();
+                    Ok(())
+                },
+            },
+        }),
+    };
+
+    static TOGGLE_MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Toggle Menu",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&POWER, &RELAY], |_| true)
+    };
+
+    static RESET: MenuItem<'_, Context> = MenuItem {
+        name: "Reset",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&EXEC_MENU),
+        menu_type: MenuItemType::ExecValue(|buf, ctx| {
+            let _ = write!(buf, "{}", ctx.uint_value);
+        },
+        |ctx| ctx.uint_value = 0,
+        Confirm::YesNo),
+    };
+
+    static ERASE: MenuItem<'_, Context> = MenuItem {
+        name: "Erase",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&EXEC_MENU),
+        menu_type: MenuItemType::ExecValue(|buf, ctx| {
+            let _ = write!(buf, "{}", ctx.bool_value);
+        },
+        |ctx| ctx.bool_value = false,
+        Confirm::TypeName),
+    };
+
+    static EXEC_MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Exec Menu",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&RESET, &ERASE], |_| true)
+    };
+
+    static PUBLIC: MenuItem<'_, Context> = MenuItem {
+        name: "Public",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&SECURE_MENU),
+        menu_type: MenuItemType::ReadValue(|buf, ctx| { let _ = write!(buf, "{}", ctx.uint_value); } ),
+    };
+
+    static CALIBRATION: MenuItem<'_, Context> = MenuItem {
+        name: "Calibration",
+        hint: None,
+        access: AccessLevel::Service,
+        parent: Some(&SECURE_MENU),
+        menu_type: MenuItemType::ReadValue(|buf, ctx| { let _ = write!(buf, "{}", ctx.bool_value); } ),
+    };
+
+    static SECURE_MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Secure Menu",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&PUBLIC, &CALIBRATION], |_| true)
+    };
+
+    static PIN: MenuItem<'_, Context> = MenuItem {
+        name: "Pin",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&SECRET_MENU),
+        menu_type: MenuItemType::Secret(|buf, ctx| {
+            let pin: u32 = buf.parse()?;
+            if pin > 9999 {
+                return Err(CallbackError::OutOfRange);
+            }
+            ctx.uint_value = pin;
+            Ok(())
+        },
+        Mask::Stars),
+    };
+
+    static KEY: MenuItem<'_, Context> = MenuItem {
+        name: "Key",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&SECRET_MENU),
+        menu_type: MenuItemType::Secret(|buf, ctx| {
+            ctx.bool_value = buf == "open sesame";
+            Ok(())
+        },
+        Mask::Hidden),
+    };
+
+    static SECRET_MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Secret Menu",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&PIN, &KEY], |_| true)
+    };
+
+    #[test]
+    fn simple() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let clock = || 0;
+        let mut runner = Dispatcher::new(&MAIN_MENU).with_refresh(&clock, 1000);
+
+        // Configure expectations
+        let expectations = [
+            // Printing Main Menu
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", MAIN_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] --> {}\r\n", SUB1.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [2] --> {}\r\n", SUB2.name)),
+            SerialTransaction::flush(),
+
+            // Printing Sub Menu 1
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", SUB1.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" <0> <-- Back to {}\r\n", MAIN_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] r=> {}: {} [{}]\r\n", BOOL_VAL.name, context.bool_value, BOOL_VAL.hint.unwrap() )),
+            SerialTransaction::flush(),
+
+            // Printing Main Menu
+            SerialTransaction::read(b'0'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", MAIN_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] --> {}\r\n", SUB1.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [2] --> {}\r\n", SUB2.name)),
+            SerialTransaction::flush(),
+
+            // Printing Sub Menu 2
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", SUB2.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" <0> <-- Back to {}\r\n", MAIN_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] r=> {}: {}\r\n", UINT_VAL.name, context.uint_value)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [2] w=> {}: {}\r\n", UINT_VAL_WRITE.name, context.uint_value)),
+            SerialTransaction::flush(),
+
+            // Printing Show UINT_VAL
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL.name, context.uint_value)),
+            SerialTransaction::flush(),
+
+            // Printing Sub Menu 2
+            SerialTransaction::read(b'\x08'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", MAIN_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] --> {}\r\n", SUB1.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [2] --> {}\r\n", SUB2.name)),
+            SerialTransaction::flush(),
+
+            // End Test
+            SerialTransaction::read(b'x'),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        serial.done();
+    }
+
+    #[test]
+    fn input() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let clock = || 0;
+        let mut runner = Dispatcher::new(&MAIN_MENU).with_refresh(&clock, 1000);
+
+        // Configure expectations
+        let expectations = [
+            // Printing Main Menu
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", MAIN_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] --> {}\r\n", SUB1.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [2] --> {}\r\n", SUB2.name)),
+            SerialTransaction::flush(),
+
+            // Printing Sub Menu 2
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", SUB2.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" <0> <-- Back to {}\r\n", MAIN_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] r=> {}: {}\r\n", UINT_VAL.name, context.uint_value)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [2] w=> {}: {}\r\n", UINT_VAL_WRITE.name, context.uint_value)),
+            SerialTransaction::flush(),
+
+            // Editting UINT_VAL_WRITE
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL_WRITE.name, context.uint_value)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"Enter new value:\r\n"),
+            SerialTransaction::flush(),
+
+            // Input
+            SerialTransaction::read(b'3'),
+            SerialTransaction::write_many("3"),
+            SerialTransaction::flush(),
+            SerialTransaction::read(BACKSPACE as u8), // backspace
+            SerialTransaction::write_many(format!("{BACKSPACE} {BACKSPACE}") ),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many("2"),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'5'),
+            SerialTransaction::write_many("5"),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'\n'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+
+            // Printing Sub Menu 2
+            SerialTransaction::write_many(format!("{}\r\n", SUB2.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" <0> <-- Back to {}\r\n", MAIN_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] r=> {}: {}\r\n", UINT_VAL.name, 25)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [2] w=> {}: {}\r\n", UINT_VAL_WRITE.name, 25)),
+            SerialTransaction::flush(),
+
+            // End Test
+            SerialTransaction::read(b'x'),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        serial.done();
+    }
+
+    #[test]
+    fn multi_digit_index() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&LARGE_MENU);
+
+        // Printing Large Menu
+        let mut expectations = vec![
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", LARGE_MENU.name)),
+            SerialTransaction::flush(),
+        ];
+        for i in 1..=17 {
+            expectations.push(SerialTransaction::write_many(format!(" [{:>2X}] r=> {}: {}\r\n", i, UINT_VAL.name, context.uint_value)));
+            expectations.push(SerialTransaction::flush());
+        }
+
+        expectations.extend(vec![
+            // Selecting item 0x11, the first digit is ambiguous
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many("1"),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many("1\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL.name, context.uint_value)),
+            SerialTransaction::flush(),
+
+            // Selecting item 2, no second digit possible
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL.name, context.uint_value)),
+            SerialTransaction::flush(),
+
+            // Selecting item 1, confirmed with enter
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many("1"),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'\r'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL.name, context.uint_value)),
+            SerialTransaction::flush(),
+
+            // End Test
+            SerialTransaction::read(b'x'),
+        ]);
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        serial.done();
+    }
+
+    /// Expectations for typing `chars` on a line.
+    fn type_chars(chars: &str) -> Vec<SerialTransaction<u8>> {
+        let mut transactions = vec![];
+        for c in chars.bytes() {
+            transactions.push(SerialTransaction::read(c));
+            transactions.push(SerialTransaction::write(c));
+            transactions.push(SerialTransaction::flush());
+        }
+        transactions
+    }
+
+    /// Expectations for typing `line` followed by enter.
+    fn type_line(line: &str) -> Vec<SerialTransaction<u8>> {
+        let mut transactions = type_chars(line);
+        transactions.push(SerialTransaction::read(b'\r'));
+        transactions.push(SerialTransaction::write_many("\r\n"));
+        transactions.push(SerialTransaction::flush());
+        transactions
+    }
+
+    #[test]
+    fn command_line() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).without_init();
+
+        let mut expectations = vec![];
+        for (line, response) in &[
+            ("get /Sub Menu 2/Uint", "Uint: 32"),
+            ("set /Sub Menu 2/Uint_Write 25", "Uint_Write: 25"),
+            ("get Sub Menu 2/Uint", "Uint: 25"),
+            ("set /Sub Menu 2/Uint_Write x", "error: Unable to parse x"),
+            ("set /Sub Menu 1/Bool true", "error: Bool is not writable"),
+            ("exec /Sub Menu 1", "error: Sub Menu 1 is not executable"),
+            ("get /Sub Menu 3", "error: no such item"),
+            ("list", "error: unknown command"),
+        ] {
+            expectations.push(SerialTransaction::read(b':'));
+            expectations.push(SerialTransaction::write(b':'));
+            expectations.push(SerialTransaction::flush());
+            expectations.extend(type_line(line));
+            expectations.push(SerialTransaction::write_many(format!("{response}\r\n")));
+            expectations.push(SerialTransaction::flush());
+        }
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        assert_eq!(context.uint_value, 25);
+        serial.done();
+    }
+
+    #[test]
+    fn tab_completion() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).without_init();
+
+        let mut expectations = vec![
+            SerialTransaction::read(b':'),
+            SerialTransaction::write(b':'),
+            SerialTransaction::flush(),
+        ];
+        expectations.extend(type_chars("get /S"));
+        expectations.extend(vec![
+            // Complete the common part of the sub menus
+            SerialTransaction::read(TAB as u8),
+            SerialTransaction::write_many("ub Menu "),
+            SerialTransaction::flush(),
+
+            // List candidates on double tab
+            SerialTransaction::read(TAB as u8),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}/  {}/  \r\n", SUB1.name, SUB2.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(":get /Sub Menu "),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_chars("2"));
+        expectations.extend(vec![
+            SerialTransaction::read(TAB as u8),
+            SerialTransaction::write_many("/"),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_chars("Uint_"));
+        expectations.extend(vec![
+            SerialTransaction::read(TAB as u8),
+            SerialTransaction::write_many("Write "),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_line(""));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL_WRITE.name, context.uint_value)),
+            SerialTransaction::flush(),
+
+            // Complete the command itself
+            SerialTransaction::read(b':'),
+            SerialTransaction::write(b':'),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_chars("s"));
+        expectations.extend(vec![
+            SerialTransaction::read(TAB as u8),
+            SerialTransaction::write_many("et "),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_line("Sub Menu 2/Uint_Write 7"));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL_WRITE.name, 7)),
+            SerialTransaction::flush(),
+
+            // End Test
+            SerialTransaction::read(b'x'),
+        ]);
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        assert_eq!(context.uint_value, 7);
+        serial.done();
+    }
+
+    /// Expectations for printing Sub Menu 2.
+    fn sub2_menu(uint_value: u32) -> Vec<SerialTransaction<u8>> {
+        vec![
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", SUB2.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" <0> <-- Back to {}\r\n", MAIN_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] r=> {}: {}\r\n", UINT_VAL.name, uint_value)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [2] w=> {}: {}\r\n", UINT_VAL_WRITE.name, uint_value)),
+            SerialTransaction::flush(),
+        ]
+    }
+
+    #[test]
+    fn history() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).without_init().with_history::<2>();
+
+        let mut expectations = vec![SerialTransaction::read(b'2')];
+        expectations.extend(sub2_menu(32));
+        expectations.extend(vec![
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL_WRITE.name, 32)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"Enter new value:\r\n"),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_line("25"));
+        expectations.extend(sub2_menu(25));
+        expectations.extend(vec![
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL_WRITE.name, 25)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"Enter new value:\r\n"),
+            SerialTransaction::flush(),
+
+            // Recall the previous value
+            SerialTransaction::read(ESCAPE as u8),
+            SerialTransaction::read(b'['),
+            SerialTransaction::read(b'A'),
+            SerialTransaction::write_many("25"),
+            SerialTransaction::flush(),
+
+            // Nothing older to recall, and nothing to flush
+            SerialTransaction::read(ESCAPE as u8),
+            SerialTransaction::read(b'['),
+            SerialTransaction::read(b'A'),
+
+            // Back to the empty line
+            SerialTransaction::read(ESCAPE as u8),
+            SerialTransaction::read(b'['),
+            SerialTransaction::read(b'B'),
+            SerialTransaction::write_many(format!("{BACKSPACE}{BACKSPACE}  {BACKSPACE}{BACKSPACE}")),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_line("7"));
+        expectations.extend(sub2_menu(7));
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        assert_eq!(context.uint_value, 7);
+        serial.done();
+    }
+
+    #[test]
+    fn line_editing() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).without_init();
+
+        let mut expectations = vec![
+            SerialTransaction::read(b':'),
+            SerialTransaction::write(b':'),
+            SerialTransaction::flush(),
+        ];
+        expectations.extend(type_chars("get Uint"));
+        expectations.extend(vec![
+            // Ctrl-W removes the word before the cursor
+            SerialTransaction::read(0x17),
+            SerialTransaction::write_many(format!("{BACKSPACE}{BACKSPACE}{BACKSPACE}{BACKSPACE}    {BACKSPACE}{BACKSPACE}{BACKSPACE}{BACKSPACE}")),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_chars("Sub Menu 2/Uint"));
+        expectations.extend(vec![
+            // Home, then move behind the 'g' and delete it
+            SerialTransaction::read(ESCAPE as u8),
+            SerialTransaction::read(b'['),
+            SerialTransaction::read(b'H'),
+            SerialTransaction::write_many(format!("{BACKSPACE}").repeat(19)),
+            SerialTransaction::flush(),
+            SerialTransaction::read(ESCAPE as u8),
+            SerialTransaction::read(b'['),
+            SerialTransaction::read(b'C'),
+            SerialTransaction::write_many("g"),
+            SerialTransaction::flush(),
+            SerialTransaction::read(ESCAPE as u8),
+            SerialTransaction::read(b'['),
+            SerialTransaction::read(b'3'),
+            SerialTransaction::read(b'~'),
+            SerialTransaction::write_many(format!("t Sub Menu 2/Uint {}", format!("{BACKSPACE}").repeat(18))),
+            SerialTransaction::flush(),
+
+            // Insert 'e' in the middle of the line
+            SerialTransaction::read(b'e'),
+            SerialTransaction::write_many(format!("et Sub Menu 2/Uint{}", format!("{BACKSPACE}").repeat(17))),
+            SerialTransaction::flush(),
+
+            // Enter works anywhere on the line
+            SerialTransaction::read(b'\r'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL.name, context.uint_value)),
+            SerialTransaction::flush(),
+
+            // End Test
+            SerialTransaction::read(b'x'),
+        ]);
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        serial.done();
+    }
+
+    #[test]
+    fn typed_value() {
+        let mut context = Context { bool_value: true, uint_value: 30 };
+
+        let mut runner = Dispatcher::new(&TYPED_MENU).without_init();
+
+        let typed_menu = |uint_value: u32| vec![
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", TYPED_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] w=> {}: {} % [0..100 step 5 %]\r\n", DUTY.name, uint_value)),
+            SerialTransaction::flush(),
+        ];
+        let select_duty = |uint_value: u32| vec![
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many(format!("{}: {} %\r\n", DUTY.name, uint_value)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many("Enter new value: [0..100 step 5 %]\r\n"),
+            SerialTransaction::flush(),
+        ];
+
+        let mut expectations = select_duty(30);
+        expectations.extend(type_line("150"));
+        expectations.push(SerialTransaction::write_many("150 is out of range [0..100 step 5 %]\r\n"));
+        expectations.push(SerialTransaction::flush());
+        expectations.extend(typed_menu(30));
+        expectations.extend(select_duty(30));
+        expectations.extend(type_line("42"));
+        expectations.push(SerialTransaction::write_many("42 is not a valid step [0..100 step 5 %]\r\n"));
+        expectations.push(SerialTransaction::flush());
+        expectations.extend(typed_menu(30));
+        expectations.extend(select_duty(30));
+        expectations.extend(type_line("45"));
+        expectations.extend(typed_menu(45));
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        assert_eq!(context.uint_value, 45);
+        serial.done();
+    }
+
+    #[test]
+    fn callback_errors() {
+        let mut context = Context { bool_value: false, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&GUARDED_MENU).without_init();
+
+        let mut expectations = vec![
+            // Errors are reported while editing
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many(format!("{}: {}\r\n", GUARDED.name, context.uint_value)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(b"Enter new value:\r\n"),
+            SerialTransaction::flush(),
+        ];
+        expectations.extend(type_line("5"));
+        expectations.extend(vec![
+            SerialTransaction::write_many("Unable to write 5: permission denied\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", GUARDED_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] w=> {}: {}\r\n", GUARDED.name, context.uint_value)),
+            SerialTransaction::flush(),
+        ]);
+
+        // And in response to commands
+        for (line, response) in &[
+            ("set Guarded 200", "error: 200 is out of range"),
+            ("set Guarded 13", "error: Unable to write 13: unlucky number"),
+            ("set Guarded x", "error: Unable to parse x"),
+        ] {
+            expectations.push(SerialTransaction::read(b':'));
+            expectations.push(SerialTransaction::write(b':'));
+            expectations.push(SerialTransaction::flush());
+            expectations.extend(type_line(line));
+            expectations.push(SerialTransaction::write_many(format!("{response}\r\n")));
+            expectations.push(SerialTransaction::flush());
+        }
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        serial.done();
+    }
+
+    #[test]
+    fn select_choice() {
+        let mut context = Context { bool_value: false, uint_value: 0 };
+
+        let mut runner = Dispatcher::new(&CHOICE_MENU).without_init();
+
+        let choice_menu = |label: &str| vec![
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", CHOICE_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] s=> {}: {} [Slow|Medium|Fast]\r\n", MODE.name, label)),
+            SerialTransaction::flush(),
+        ];
+        let choices = |current: usize| {
+            let mut transactions = vec![
+                SerialTransaction::read(b'1'),
+                SerialTransaction::write_many("\r\n"),
+                SerialTransaction::flush(),
+                SerialTransaction::write_many(format!("{}\r\n", MODE.name)),
+                SerialTransaction::flush(),
+                SerialTransaction::write_many(format!(" <0> <-- Back to {}\r\n", CHOICE_MENU.name)),
+                SerialTransaction::flush(),
+            ];
+            for (i, label) in ["Slow", "Medium", "Fast"].iter().enumerate() {
+                let mark = if i == current { '*' } else { ' ' };
+                transactions.push(SerialTransaction::write_many(format!(" [{}] ({}) {}\r\n", i + 1, mark, label)));
+                transactions.push(SerialTransaction::flush());
+            }
+            transactions
+        };
+
+        // Pick a choice by its index
+        let mut expectations = choices(0);
+        expectations.push(SerialTransaction::read(b'2'));
+        expectations.extend(choice_menu("Medium"));
+
+        // A refused choice keeps the current one
+        expectations.extend(choices(1));
+        expectations.push(SerialTransaction::read(b'3'));
+        expectations.push(SerialTransaction::write_many("Unable to write Fast: permission denied\r\n"));
+        expectations.push(SerialTransaction::flush());
+        expectations.extend(choice_menu("Medium"));
+
+        // Going back leaves the choice untouched
+        expectations.extend(choices(1));
+        expectations.push(SerialTransaction::read(b'0'));
+        expectations.extend(choice_menu("Medium"));
+
+        // Choices can also be set by label
+        expectations.push(SerialTransaction::read(b':'));
+        expectations.push(SerialTransaction::write(b':'));
+        expectations.push(SerialTransaction::flush());
+        expectations.extend(type_line("set Mode slow"));
+        expectations.push(SerialTransaction::write_many("Mode: Slow\r\n"));
+        expectations.push(SerialTransaction::flush());
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        assert_eq!(context.uint_value, 0);
+        serial.done();
+    }
+
+    #[test]
+    fn toggle() {
+        let mut context = Context { bool_value: true, uint_value: 0 };
+
+        let mut runner = Dispatcher::new(&TOGGLE_MENU).without_init();
+
+        let toggle_menu = |power: &str, relay: &str| vec![
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", TOGGLE_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] t=> {}: {} [off|on]\r\n", POWER.name, power)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [2] t=> {}: {} [open|closed]\r\n", RELAY.name, relay)),
+            SerialTransaction::flush(),
+        ];
+
+        // A single key press flips the flag
+        let mut expectations = vec![SerialTransaction::read(b'1')];
+        expectations.extend(toggle_menu("off", "open"));
+
+        // Guarded flags ask first, anything but 'y' declines
+        for answer in b"n\rY" {
+            expectations.extend(vec![
+                SerialTransaction::read(b'2'),
+                SerialTransaction::write_many(format!("Set {} to closed? y/N ", RELAY.name)),
+                SerialTransaction::flush(),
+                SerialTransaction::read(*answer),
+            ]);
+            if *answer != b'\r' {
+                expectations.push(SerialTransaction::write(*answer));
+            }
+            expectations.push(SerialTransaction::write_many("\r\n"));
+            expectations.push(SerialTransaction::flush());
+            expectations.extend(toggle_menu("off", if *answer == b'Y' { "closed" } else { "open" }));
+        }
+
+        // Commands flip or set unguarded flags right away
+        for (line, response) in &[("exec Power", "Power: on"), ("set Power sure", "error: Unable to parse sure")] {
+            expectations.push(SerialTransaction::read(b':'));
+            expectations.push(SerialTransaction::write(b':'));
+            expectations.push(SerialTransaction::flush());
+            expectations.extend(type_line(line));
+            expectations.push(SerialTransaction::write_many(format!("{response}\r\n")));
+            expectations.push(SerialTransaction::flush());
+        }
+
+        // Guarded flags still ask before a command changes them
+        expectations.extend(vec![SerialTransaction::read(b':'), SerialTransaction::write(b':'), SerialTransaction::flush()]);
+        expectations.extend(type_line("set Relay open"));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("Set {} to open? y/N ", RELAY.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'y'),
+            SerialTransaction::write_many("y\r\n"),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(toggle_menu("on", "open"));
+        expectations.extend(vec![SerialTransaction::read(b':'), SerialTransaction::write(b':'), SerialTransaction::flush()]);
+        expectations.extend(type_line("set Relay open"));
+        expectations.push(SerialTransaction::write_many(format!("{}: open\r\n", RELAY.name)));
+        expectations.push(SerialTransaction::flush());
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        assert!(context.bool_value);
+        assert_eq!(context.uint_value, 0);
+        serial.done();
+    }
+
+    #[test]
+    fn confirm_exec() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&EXEC_MENU).without_init();
+
+        let mut expectations = vec![
+            // Declined
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many("Are you sure? y/N "),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'\r'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many("Cancelled\r\n"),
+            SerialTransaction::flush(),
+
+            // Confirmed
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many("Are you sure? y/N "),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'y'),
+            SerialTransaction::write_many("y\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many("> 0\r\n"),
+            SerialTransaction::flush(),
+        ];
+
+        // Only the exact name confirms
+        for (answer, response) in &[("erase", "Cancelled"), ("Erase", "> false")] {
+            expectations.extend(vec![
+                SerialTransaction::read(b'2'),
+                SerialTransaction::write_many("Are you sure? Type 'Erase' to confirm: "),
+                SerialTransaction::flush(),
+            ]);
+            expectations.extend(type_line(answer));
+            expectations.push(SerialTransaction::write_many(format!("{response}\r\n")));
+            expectations.push(SerialTransaction::flush());
+        }
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        assert_eq!(context.uint_value, 0);
+        assert!(!context.bool_value);
+        serial.done();
+    }
+
+    #[test]
+    fn confirm_command() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&EXEC_MENU).without_init();
+
+        // Commands ask the same questions as the menu
+        let mut expectations = vec![SerialTransaction::read(b':'), SerialTransaction::write(b':'), SerialTransaction::flush()];
+        expectations.extend(type_line("exec Reset"));
+        expectations.extend(vec![
+            SerialTransaction::write_many("Are you sure? y/N "),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'n'),
+            SerialTransaction::write_many("n\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many("Cancelled\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b':'),
+            SerialTransaction::write(b':'),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_line("exec /Erase"));
+        expectations.extend(vec![
+            SerialTransaction::write_many("Are you sure? Type 'Erase' to confirm: "),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_line("Erase"));
+        expectations.push(SerialTransaction::write_many("> false\r\n"));
+        expectations.push(SerialTransaction::flush());
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        assert_eq!(context.uint_value, 32);
+        assert!(!context.bool_value);
+        serial.done();
+    }
+
+    #[test]
+    fn login() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+        let now = core::cell::Cell::new(0);
+        let clock = || now.get();
+
+        let mut runner = Dispatcher::new(&SECURE_MENU).without_init().with_login(Login {
+            check: |password, _| if password == "1234" { Some(AccessLevel::Service) } else { None },
+            clock: &clock,
+            timeout_ms: 1000,
+            max_attempts: 2,
+            lockout_ms: 5000,
+        });
+
+        let secure_menu = |unlocked: bool| vec![
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", SECURE_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] r=> {}: {}\r\n", PUBLIC.name, 32)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(&if unlocked {
+                format!(" [2] r=> {}: true\r\n", CALIBRATION.name)
+            } else {
+                format!(" [2] r=> {} (Locked)\r\n", CALIBRATION.name)
+            }),
+            SerialTransaction::flush(),
+        ];
+        let password = |password: &str, response: &str| {
+            let mut transactions = vec![
+                SerialTransaction::write_many("Password: "),
+                SerialTransaction::flush(),
+            ];
+            for c in password.bytes() {
+                transactions.push(SerialTransaction::read(c));
+                transactions.push(SerialTransaction::write(b'*'));
+                transactions.push(SerialTransaction::flush());
+            }
+            transactions.push(SerialTransaction::read(b'\r'));
+            transactions.push(SerialTransaction::write_many("\r\n"));
+            transactions.push(SerialTransaction::flush());
+            transactions.push(SerialTransaction::write_many(format!("{response}\r\n")));
+            transactions.push(SerialTransaction::flush());
+            transactions
+        };
+        let login = |pw: &str, response: &str, unlocked: bool| {
+            let mut transactions = vec![SerialTransaction::read(b':'), SerialTransaction::write(b':'), SerialTransaction::flush()];
+            transactions.extend(type_line("login"));
+            transactions.extend(password(pw, response));
+            transactions.extend(secure_menu(unlocked));
+            transactions
+        };
+        let run = |runner: &mut Dispatcher<'_, Context>, context: &mut Context, mut expectations: Vec<SerialTransaction<u8>>| {
+            // End Test
+            expectations.push(SerialTransaction::read(b'x'));
+
+            let mut serial = SerialMock::new(&expectations);
+            match runner.run(context, &mut Lines::new(&mut serial)) {
+                Ok(()) => {}
+                Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+            }
+            serial.done();
+        };
+
+        // Selecting a locked item asks for a password, which is never echoed
+        let mut expectations = vec![
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many(format!("{} requires service access\r\n", CALIBRATION.name)),
+            SerialTransaction::flush(),
+        ];
+        expectations.extend(password("12", "Access denied"));
+        expectations.extend(secure_menu(false));
+        expectations.extend(login("1234", "Logged in with service access", true));
+        run(&mut runner, &mut context, expectations);
+
+        // The session expires after a second without input, a second failure locks logins out
+        now.set(1000);
+        let mut expectations = vec![
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many("Session expired\r\n"),
+            SerialTransaction::flush(),
+        ];
+        expectations.extend(secure_menu(false));
+        expectations.extend(login("0", "Access denied", false));
+        expectations.extend(login("0", "Access denied", false));
+        expectations.extend(login("1234", "Too many attempts, try again later", false));
+        run(&mut runner, &mut context, expectations);
+
+        now.set(6000);
+        let mut expectations = login("1234", "Logged in with service access", true);
+        expectations.extend(vec![SerialTransaction::read(b':'), SerialTransaction::write(b':'), SerialTransaction::flush()]);
+        expectations.extend(type_line("logout"));
+        expectations.push(SerialTransaction::write_many("Logged out\r\n"));
+        expectations.push(SerialTransaction::flush());
+        run(&mut runner, &mut context, expectations);
+    }
+
+    #[test]
+    fn secret() {
+        let mut context = Context { bool_value: true, uint_value: 0 };
+
+        let mut runner = Dispatcher::new(&SECRET_MENU).without_init();
+
+        let secret_menu = || vec![
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", SECRET_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] w=> {}\r\n", PIN.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [2] w=> {}\r\n", KEY.name)),
+            SerialTransaction::flush(),
+        ];
+        let select = |idx: u8, item: &MenuItem<'_, Context>| vec![
+            SerialTransaction::read(idx),
+            SerialTransaction::write_many(format!("{}:\r\n", item.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many("Enter new value: "),
+            SerialTransaction::flush(),
+        ];
+        let enter = || vec![
+            SerialTransaction::read(b'\r'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+        ];
+
+        // Stars are echoed instead of the value, and never read back
+        let mut expectations = select(b'1', &PIN);
+        for c in "42".bytes() {
+            expectations.extend(vec![SerialTransaction::read(c), SerialTransaction::write(b'*'), SerialTransaction::flush()]);
+        }
+        expectations.extend(vec![
+            SerialTransaction::read(BACKSPACE as u8),
+            SerialTransaction::write_many(format!("{BACKSPACE} {BACKSPACE}")),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'3'),
+            SerialTransaction::write(b'*'),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(enter());
+        expectations.extend(secret_menu());
+
+        // Nothing at all is echoed
+        expectations.extend(select(b'2', &KEY));
+        for c in "abc".bytes() {
+            expectations.push(SerialTransaction::read(c));
+        }
+        expectations.extend(enter());
+        expectations.extend(secret_menu());
+
+        // Errors do not repeat the input
+        expectations.extend(select(b'1', &PIN));
+        for c in "12345".bytes() {
+            expectations.extend(vec![SerialTransaction::read(c), SerialTransaction::write(b'*'), SerialTransaction::flush()]);
+        }
+        expectations.extend(enter());
+        expectations.push(SerialTransaction::write_many("value is out of range\r\n"));
+        expectations.push(SerialTransaction::flush());
+        expectations.extend(secret_menu());
+
+        // Keys beyond the room for a secret are not taken
+        expectations.extend(select(b'1', &PIN));
+        for c in format!("{:0>32}", 7).bytes() {
+            expectations.extend(vec![SerialTransaction::read(c), SerialTransaction::write(b'*'), SerialTransaction::flush()]);
+        }
+        for c in "99999999".bytes() {
+            expectations.push(SerialTransaction::read(c));
+        }
+        expectations.extend(enter());
+        expectations.extend(secret_menu());
+
+        // Secrets never pass through the command line
+        for (line, response) in &[("get Pin", "error: Pin is write only"), ("set Pin 1234", "error: Pin can only be set from the menu")] {
+            expectations.push(SerialTransaction::read(b':'));
+            expectations.push(SerialTransaction::write(b':'));
+            expectations.push(SerialTransaction::flush());
+            expectations.extend(type_line(line));
+            expectations.push(SerialTransaction::write_many(format!("{response}\r\n")));
+            expectations.push(SerialTransaction::flush());
+        }
+
+        // Nor are they kept in the history
+        expectations.extend(vec![
+            SerialTransaction::read(b':'),
+            SerialTransaction::write(b':'),
+            SerialTransaction::flush(),
+            SerialTransaction::read(ESCAPE as u8),
+            SerialTransaction::read(b'['),
+            SerialTransaction::read(b'A'),
+        ]);
+        expectations.extend(enter());
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        assert_eq!(context.uint_value, 7);
+        assert!(!context.bool_value);
+        serial.done();
+    }
+
+    static LONG_VAL: MenuItem<'_, Context> = MenuItem {
+        name: "A value with a name that does not fit in a single output buffer",
+        hint: None,
+        access: AccessLevel::User,
+        parent: Some(&LONG_MENU),
+        menu_type: MenuItemType::ReadValue(|buf, ctx| { let _ = write!(buf, "{}", ctx.uint_value); } ),
+    };
+
+    static LONG_MENU: MenuItem<'_, Context> = MenuItem {
+        name: "Long",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&LONG_VAL], |_| true)
+    };
+
+    #[test]
+    fn buffered_output() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&LONG_MENU).with_output_buffer::<16>();
+
+        // Each render is written in chunks and flushed once, lines longer than the buffer included
+        let expectations = [
+            SerialTransaction::write_many(format!("\r\n{}\r\n [1] r=> {}: {}\r\n", LONG_MENU.name, LONG_VAL.name, 32)),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many(format!("{}: {}\r\n", LONG_VAL.name, 32)),
+            SerialTransaction::flush(),
+
+            // End Test
+            SerialTransaction::read(b'x'),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.run(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
 
     #[test]
-    fn simple() {
+    fn blocked_transmitter() {
         let mut context = Context { bool_value: true, uint_value: 32 };
 
-        let mut runner = Dispatcher::new(&MAIN_MENU).with_refresh();
+        let mut runner = Dispatcher::new(&LONG_MENU).with_output_buffer::<16>();
 
-        // Configure expectations
+        let menu = format!("\r\n{}\r\n [1] r=> {}: {}\r\n", LONG_MENU.name, LONG_VAL.name, 32);
+        let busy = || SerialTransaction::write_error(menu.as_bytes()[20], nb::Error::WouldBlock);
         let expectations = [
-            // Printing Main Menu
-            SerialTransaction::write_many("\r\n"),
+            // The transmitter is busy while the render fills the buffer, which is written out once it is free again
+            SerialTransaction::write_many(&menu[..20]),
+            busy(),
+            busy(),
+            busy(),
+            SerialTransaction::write_many(&menu[20..32]),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!("{}\r\n", MAIN_MENU.name)),
+
+            // The next poll continues the render where it stopped, a line longer than the buffer is rendered again
+            SerialTransaction::write_many(&menu[32..menu.len() - 4]),
+            SerialTransaction::write_many("7\r\n"),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [1] --> {}\r\n", SUB1.name)),
+
+            // End Test
+            SerialTransaction::read(b'x'),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        context.uint_value = 7;
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    #[test]
+    fn blocked_short_lines() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&SUB2).with_output_buffer::<32>();
+
+        let menu = format!("\r\n{}\r\n <0> <-- Back to {}\r\n [1] r=> {}: 32\r\n [2] w=> {}: 32\r\n",
+                           SUB2.name, MAIN_MENU.name, UINT_VAL.name, UINT_VAL_WRITE.name);
+        let busy = || SerialTransaction::write_error(menu.as_bytes()[40], nb::Error::WouldBlock);
+        let expectations = [
+            SerialTransaction::write_many(&menu[..40]),
+            busy(),
+            busy(),
+            busy(),
+            SerialTransaction::write_many(&menu[40..64]),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [2] --> {}\r\n", SUB2.name)),
+
+            // A line that fits in the buffer carries on as it was first rendered
+            SerialTransaction::write_many(&menu[64..]),
             SerialTransaction::flush(),
 
-            // Printing Sub Menu 1
-            SerialTransaction::read(b'1'),
+            // End Test
+            SerialTransaction::read(b'x'),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        context.uint_value = 7;
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    #[test]
+    fn theme() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).with_theme(&Theme::COLOUR);
+
+        let mut expectations = vec![
             SerialTransaction::write_many("\r\n"),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!("{}\r\n", SUB1.name)),
+            SerialTransaction::write_many(format!("\x1b[1;4m{}\x1b[0m\r\n", MAIN_MENU.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" \x1b[1m[1]\x1b[0m \x1b[34m-->\x1b[0m {}\r\n", SUB1.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" \x1b[1m[2]\x1b[0m \x1b[34m-->\x1b[0m {}\r\n", SUB2.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" <0> --> {}\r\n", MAIN_MENU.name)),
+            SerialTransaction::read(b':'),
+            SerialTransaction::write(b':'),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [1] r=> {}: {} [{}]\r\n", BOOL_VAL.name, context.bool_value, BOOL_VAL.hint.unwrap() )),
+        ];
+        expectations.extend(type_line("get Nothing"));
+        expectations.extend(vec![
+            SerialTransaction::write_many("\x1b[1;31merror:\x1b[0m no such item\r\n"),
             SerialTransaction::flush(),
+        ]);
 
-            // Printing Main Menu
-            SerialTransaction::read(b'0'),
-            SerialTransaction::write_many("\r\n"),
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        match runner.run(&mut context, &mut Lines::new(&mut serial)) {
+            Ok(()) => {}
+            Err(e1) => assert_eq!(e1, nb::Error::WouldBlock),
+        }
+        serial.done();
+    }
+
+    #[test]
+    fn full_screen() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).with_screen::<32>();
+
+        let mut expectations = vec![
+            // Line mode until the terminal answers
+            SerialTransaction::write_many(format!("\x1b7\x1b[999;999H\x1b[6n\x1b8\r\n{}\r\n [1] --> {}\r\n [2] --> {}\r\n", MAIN_MENU.name, SUB1.name, SUB2.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!("{}\r\n", MAIN_MENU.name)),
+        ];
+        expectations.extend("\x1b[24;80R".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b[H\x1b[2J\r\n{}\r\n>[1] --> {}\r\n [2] --> {}\r\n", MAIN_MENU.name, SUB1.name, SUB2.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [1] --> {}\r\n", SUB1.name)),
+        ]);
+
+        // Only the rows that changed are redrawn
+        expectations.extend("\x1b[B".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b7\x1b[3;1H\x1b[2K [1] --> {}\r\n\x1b[4;1H\x1b[2K>[2] --> {}\r\n\x1b8", SUB1.name, SUB2.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [2] --> {}\r\n", SUB2.name)),
+        ]);
+
+        expectations.extend("\r".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b[5;1H\x1b[J\x1b[H\x1b[2J\r\n{}\r\n <0> <-- Back to {}\r\n>[1] r=> {}: 32\r\n [2] w=> {}: 32\r\n",
+                                                   SUB2.name, MAIN_MENU.name, UINT_VAL.name, UINT_VAL_WRITE.name)),
             SerialTransaction::flush(),
+        ]);
 
-            // Printing Sub Menu 2
-            SerialTransaction::read(b'2'),
-            SerialTransaction::write_many("\r\n"),
+        expectations.extend("\x1b[A".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b7\x1b[3;1H\x1b[2K><0> <-- Back to {}\r\n\x1b[4;1H\x1b[2K [1] r=> {}: 32\r\n\x1b8", MAIN_MENU.name, UINT_VAL.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!("{}\r\n", SUB2.name)),
+        ]);
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.run(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    #[test]
+    fn screen_without_rows() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).with_screen::<0>();
+
+        // Without rows to remember the terminal is not asked, and an answer anyway changes nothing
+        let mut expectations = vec![
+            SerialTransaction::write_many(format!("\r\n{}\r\n [1] --> {}\r\n [2] --> {}\r\n", MAIN_MENU.name, SUB1.name, SUB2.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" <0> --> {}\r\n", MAIN_MENU.name)),
+        ];
+        expectations.extend("\x1b[24;80R".bytes().map(SerialTransaction::read));
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.run(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    #[test]
+    fn screen_scrolling() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&LARGE_MENU).with_screen::<32>();
+
+        let entry = |i: usize, selected: bool| format!("{}[{:>2X}] r=> {}: {}\r\n", if selected { '>' } else { ' ' }, i, UINT_VAL.name, 32);
+
+        let mut menu = format!("\x1b7\x1b[999;999H\x1b[6n\x1b8\r\n{}\r\n", LARGE_MENU.name);
+        for i in 1..=17 {
+            menu += &entry(i, false);
+        }
+        let mut expectations = vec![
+            SerialTransaction::write_many(&menu),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [1] r=> {}: {}\r\n", UINT_VAL.name, context.uint_value)),
+        ];
+
+        // Six rows leave room for two entries below the header
+        expectations.extend("\x1b[6;80R".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b[H\x1b[2J\r\n{}\r\n{}{}", LARGE_MENU.name, entry(1, true), entry(2, false))),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [2] w=> {}: {}\r\n", UINT_VAL_WRITE.name, context.uint_value)),
+        ]);
+
+        expectations.extend("\x1b[B".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b7\x1b[3;1H\x1b[2K{}\x1b[4;1H\x1b[2K{}\x1b8", entry(1, false), entry(2, true))),
             SerialTransaction::flush(),
+        ]);
 
-            // Printing Show UINT_VAL
-            SerialTransaction::read(b'1'),
-            SerialTransaction::write_many(&format!("{}: {}\r\n", UINT_VAL.name, context.uint_value)),
+        // Moving past the last row scrolls the menu
+        expectations.extend("\x1b[B".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b7\x1b[3;1H\x1b[2K{}\x1b[4;1H\x1b[2K{}\x1b8", entry(2, false), entry(3, true))),
             SerialTransaction::flush(),
+        ]);
 
-            // Printing Sub Menu 2
-            SerialTransaction::read(b'\x08'),
-            SerialTransaction::write_many("\r\n"),
+        expectations.extend("\x1b[A".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b7\x1b[3;1H\x1b[2K{}\x1b[4;1H\x1b[2K{}\x1b8", entry(2, true), entry(3, false))),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend("\x1b[A".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b7\x1b[3;1H\x1b[2K{}\x1b[4;1H\x1b[2K{}\x1b8", entry(1, true), entry(2, false))),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!("{}\r\n", MAIN_MENU.name)),
+        ]);
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.run(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    #[test]
+    fn watch() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+        let now = core::cell::Cell::new(0);
+        let clock = || now.get();
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).without_init().with_watch(Watch { clock: &clock, interval_ms: 100 });
+
+        let values = |uint_value: u32| format!("\x1b[2K{}: {}\r\n\x1b[2K{}: {}\r\n", UINT_VAL.name, uint_value, UINT_VAL_WRITE.name, uint_value);
+
+        let mut expectations = type_chars(":watch /Sub Menu 2");
+        expectations.extend(vec![
+            SerialTransaction::read(b'\r'),
+            SerialTransaction::write_many(format!("\r\nWatching {}, press any key to stop\r\n{}", SUB2.name, values(32))),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [1] --> {}\r\n", SUB1.name)),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+
+            // Nothing is read again before the interval has passed
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+
+            SerialTransaction::write_many(format!("\x1b[2F{}", values(33))),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [2] --> {}\r\n", SUB2.name)),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+
+            // Any key stops watching
+            SerialTransaction::read(b' '),
+            SerialTransaction::write_many(format!("\r\n{}\r\n [1] --> {}\r\n [2] --> {}\r\n", MAIN_MENU.name, SUB1.name, SUB2.name)),
             SerialTransaction::flush(),
 
             // End Test
             SerialTransaction::read(b'x'),
-        ];
+        ]);
 
         let mut serial = SerialMock::new(&expectations);
 
-        match runner.run(&mut context, &mut serial) {
-            Ok(_) => {}
-            Err(e1) => {
-                if e1 != nb::Error::WouldBlock {
-                    panic!("Error: {:?}", e1);
-                }
-            }
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        now.set(99);
+        context.uint_value = 33;
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        now.set(100);
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    #[test]
+    fn watch_nothing() {
+        let mut context = Context { bool_value: false, uint_value: 32 };
+        let now = core::cell::Cell::new(0);
+        let clock = || now.get();
+
+        // Neither a disabled menu nor a write only item are watched, and there is no notice saying they are
+        for (menu, line, response) in &[(&TREE, "watch Branch", "error: Branch is disabled"), (&SECRET_MENU, "watch Pin", "error: Pin has no values")] {
+            let mut expectations = vec![SerialTransaction::read(b':'), SerialTransaction::write(b':'), SerialTransaction::flush()];
+            expectations.extend(type_line(line));
+            expectations.push(SerialTransaction::write_many(format!("{response}\r\n")));
+            expectations.push(SerialTransaction::flush());
+            expectations.push(SerialTransaction::read(b'x'));
+
+            let mut runner = Dispatcher::new(*menu).without_init().with_watch(Watch { clock: &clock, interval_ms: 100 });
+            let mut serial = SerialMock::new(&expectations);
+            assert_eq!(runner.poll(&mut context, &mut Lines::new(&mut serial)), Err(nb::Error::WouldBlock));
+            serial.done();
         }
     }
 
     #[test]
-    fn input() {
+    fn refresh() {
         let mut context = Context { bool_value: true, uint_value: 32 };
+        let now = core::cell::Cell::new(0);
+        let clock = || now.get();
 
-        let mut runner = Dispatcher::new(&MAIN_MENU).with_refresh();
+        let mut runner = Dispatcher::new(&SUB2).with_refresh(&clock, 100);
+
+        let menu = |uint_value: u32| format!("\r\n{}\r\n <0> <-- Back to {}\r\n [1] r=> {}: {}\r\n [2] w=> {}: {}\r\n",
+                                             SUB2.name, MAIN_MENU.name, UINT_VAL.name, uint_value, UINT_VAL_WRITE.name, uint_value);
 
-        // Configure expectations
         let expectations = [
-            // Printing Main Menu
-            SerialTransaction::write_many("\r\n"),
-            SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!("{}\r\n", MAIN_MENU.name)),
+            SerialTransaction::write_many(menu(32)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [1] --> {}\r\n", SUB1.name)),
-            SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [2] --> {}\r\n", SUB2.name)),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+
+            // Nothing changed, nothing printed
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+
+            SerialTransaction::write_many(menu(33)),
             SerialTransaction::flush(),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
 
-            // Printing Sub Menu 2
+            // Not while entering a value
             SerialTransaction::read(b'2'),
-            SerialTransaction::write_many("\r\n"),
-            SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!("{}\r\n", SUB2.name)),
+            SerialTransaction::write_many(format!("{}: 33\r\nEnter new value:\r\n", UINT_VAL_WRITE.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" <0> --> {}\r\n", MAIN_MENU.name)),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        now.set(100);
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        now.set(200);
+        context.uint_value = 33;
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        now.set(300);
+        context.uint_value = 34;
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    #[test]
+    fn idle_timeout() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+        let now = core::cell::Cell::new(0);
+        let clock = || now.get();
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).without_init().with_idle_timeout(Idle { clock: &clock, timeout_ms: 1000, logout: true });
+
+        let expectations = [
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many(format!("\r\n{}\r\n <0> <-- Back to {}\r\n [1] r=> {}: 32\r\n [2] w=> {}: 32\r\n",
+                                                   SUB2.name, MAIN_MENU.name, UINT_VAL.name, UINT_VAL_WRITE.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [1] r=> {}: {}\r\n", UINT_VAL.name, context.uint_value)),
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many(format!("{}: 32\r\nEnter new value:\r\n", UINT_VAL_WRITE.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [2] w=> {}: {}\r\n", UINT_VAL_WRITE.name, context.uint_value)),
+            SerialTransaction::read(b'4'),
+            SerialTransaction::write(b'4'),
             SerialTransaction::flush(),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
 
-            // Editting UINT_VAL_WRITE
+            // Typing keeps it going
             SerialTransaction::read(b'2'),
-            SerialTransaction::write_many(&format!("{}: {}\r\n", UINT_VAL_WRITE.name, context.uint_value)),
+            SerialTransaction::write(b'2'),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(b"Enter new value:\r\n"),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+
+            // The value is discarded, back to the root
+            SerialTransaction::write_many(format!("\r\nInactivity timeout\r\n\r\n{}\r\n [1] --> {}\r\n [2] --> {}\r\n", MAIN_MENU.name, SUB1.name, SUB2.name)),
             SerialTransaction::flush(),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
 
-            // Input
-            SerialTransaction::read(b'3'),
-            SerialTransaction::write_many("3"),
+            // Nothing to do at the root
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+        ];
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        now.set(900);
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        now.set(1900);
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        now.set(5000);
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        assert_eq!(context.uint_value, 32);
+        serial.done();
+    }
+
+    static ORPHAN_C: MenuItem<'_, Context> = MenuItem {
+        name: "Level C",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&UINT_VAL], |_| true)
+    };
+
+    static ORPHAN_B: MenuItem<'_, Context> = MenuItem {
+        name: "Level B",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&ORPHAN_C], |_| true)
+    };
+
+    static ORPHAN_A: MenuItem<'_, Context> = MenuItem {
+        name: "Level A",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&ORPHAN_B], |_| true)
+    };
+
+    static ORPHANS: MenuItem<'_, Context> = MenuItem {
+        name: "Orphans",
+        hint: None,
+        access: AccessLevel::User,
+        parent: None,
+        menu_type: MenuItemType::SubMenu(&[&ORPHAN_A], |_| true)
+    };
+
+    /// Reading `key` prints `menu`, with the way back to `back` and its single entry `child`.
+    fn orphan_menu(key: u8, menu: &MenuItem<'_, Context>, back: Option<&MenuItem<'_, Context>>, child: &str) -> Vec<SerialTransaction<u8>> {
+        let mut transactions = vec![
+            SerialTransaction::read(key),
+            SerialTransaction::write_many("\r\n"),
             SerialTransaction::flush(),
+            SerialTransaction::write_many(format!("{}\r\n", menu.name)),
             SerialTransaction::flush(),
-            SerialTransaction::read(BACKSPACE as u8), // backspace
-            SerialTransaction::write_many(format!("{bs} {bs}", bs=BACKSPACE) ),
+        ];
+        if let Some(back) = back {
+            transactions.push(SerialTransaction::write_many(format!(" <0> <-- Back to {}\r\n", back.name)));
+            transactions.push(SerialTransaction::flush());
+        }
+        transactions.push(SerialTransaction::write_many(format!(" [1] {child}\r\n")));
+        transactions.push(SerialTransaction::flush());
+        transactions
+    }
+
+    #[test]
+    fn without_parents() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&ORPHANS).without_init();
+
+        // Down to the bottom and back, none of the submenus know their parent
+        let mut expectations = orphan_menu(b'1', &ORPHAN_A, Some(&ORPHANS), "--> Level B");
+        expectations.extend(orphan_menu(b'1', &ORPHAN_B, Some(&ORPHAN_A), "--> Level C"));
+        expectations.extend(orphan_menu(b'1', &ORPHAN_C, Some(&ORPHAN_B), "r=> Uint: 32"));
+        expectations.extend(orphan_menu(b'0', &ORPHAN_B, Some(&ORPHAN_A), "--> Level C"));
+        expectations.extend(orphan_menu(b'0', &ORPHAN_A, Some(&ORPHANS), "--> Level B"));
+        expectations.extend(orphan_menu(b'0', &ORPHANS, None, "--> Level A"));
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.run(&mut context, &mut Lines::new(&mut serial)), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    #[test]
+    fn depth_limit() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&ORPHANS).without_init().with_depth::<1>();
+
+        // Only the last menu is remembered, going back from Level B skips Level A
+        let mut expectations = orphan_menu(b'1', &ORPHAN_A, Some(&ORPHANS), "--> Level B");
+        expectations.extend(orphan_menu(b'1', &ORPHAN_B, Some(&ORPHAN_A), "--> Level C"));
+        expectations.extend(orphan_menu(b'1', &ORPHAN_C, Some(&ORPHAN_B), "r=> Uint: 32"));
+        expectations.extend(orphan_menu(b'0', &ORPHAN_B, Some(&ORPHANS), "--> Level C"));
+        expectations.extend(orphan_menu(b'0', &ORPHANS, None, "--> Level A"));
+
+        // End Test
+        expectations.push(SerialTransaction::read(b'x'));
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.run(&mut context, &mut Lines::new(&mut serial)), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    #[test]
+    fn idle_logout() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+        let now = core::cell::Cell::new(0);
+        let clock = || now.get();
+
+        let mut runner = Dispatcher::new(&SECURE_MENU).without_init().with_login(Login {
+            check: |password, _| if password == "1234" { Some(AccessLevel::Service) } else { None },
+            clock: &clock,
+            timeout_ms: 60_000,
+            max_attempts: 2,
+            lockout_ms: 5000,
+        }).with_idle_timeout(Idle { clock: &clock, timeout_ms: 1000, logout: true });
+
+        let secure_menu = |unlocked: bool| vec![
+            SerialTransaction::write_many("\r\n"),
             SerialTransaction::flush(),
-            SerialTransaction::read(b'2'),
-            SerialTransaction::write_many("2"),
+            SerialTransaction::write_many(format!("{}\r\n", SECURE_MENU.name)),
             SerialTransaction::flush(),
+            SerialTransaction::write_many(format!(" [1] r=> {}: {}\r\n", PUBLIC.name, 32)),
             SerialTransaction::flush(),
-            SerialTransaction::read(b'5'),
-            SerialTransaction::write_many("5"),
+            SerialTransaction::write_many(&if unlocked {
+                format!(" [2] r=> {}: true\r\n", CALIBRATION.name)
+            } else {
+                format!(" [2] r=> {} (Locked)\r\n", CALIBRATION.name)
+            }),
             SerialTransaction::flush(),
+        ];
+
+        let mut expectations = vec![SerialTransaction::read(b':'), SerialTransaction::write(b':'), SerialTransaction::flush()];
+        expectations.extend(type_line("login"));
+        expectations.extend(vec![
+            SerialTransaction::write_many("Password: "),
             SerialTransaction::flush(),
-            SerialTransaction::read(b'\n'),
+        ]);
+        for c in "1234".bytes() {
+            expectations.extend(vec![SerialTransaction::read(c), SerialTransaction::write(b'*'), SerialTransaction::flush()]);
+        }
+        expectations.extend(vec![
+            SerialTransaction::read(b'\r'),
             SerialTransaction::write_many("\r\n"),
             SerialTransaction::flush(),
+            SerialTransaction::write_many("Logged in with service access\r\n"),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(secure_menu(true));
+        expectations.push(SerialTransaction::read_error(nb::Error::WouldBlock));
+
+        // Idling at the root still ends the session
+        expectations.extend(vec![
             SerialTransaction::write_many("\r\n"),
             SerialTransaction::flush(),
+            SerialTransaction::write_many("Inactivity timeout\r\n"),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(secure_menu(false));
+        expectations.push(SerialTransaction::read_error(nb::Error::WouldBlock));
 
-            // Printing Sub Menu 2
-            SerialTransaction::write_many(&format!("{}\r\n", SUB2.name)),
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.poll(&mut context, &mut Lines::new(&mut serial)), Err(nb::Error::WouldBlock));
+        now.set(1000);
+        assert_eq!(runner.poll(&mut context, &mut Lines::new(&mut serial)), Err(nb::Error::WouldBlock));
+        assert_eq!(runner.level(), AccessLevel::User);
+        serial.done();
+    }
+
+    #[test]
+    fn idle_timeout_screen() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+        let now = core::cell::Cell::new(0);
+        let clock = || now.get();
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).with_screen::<32>().with_idle_timeout(Idle { clock: &clock, timeout_ms: 1000, logout: false });
+
+        let mut expectations = vec![
+            SerialTransaction::write_many(format!("\x1b7\x1b[999;999H\x1b[6n\x1b8\r\n{}\r\n [1] --> {}\r\n [2] --> {}\r\n", MAIN_MENU.name, SUB1.name, SUB2.name)),
+            SerialTransaction::flush(),
+        ];
+        expectations.extend("\x1b[24;80R".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b[H\x1b[2J\r\n{}\r\n>[1] --> {}\r\n [2] --> {}\r\n", MAIN_MENU.name, SUB1.name, SUB2.name)),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend("\x1b[B".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b7\x1b[3;1H\x1b[2K [1] --> {}\r\n\x1b[4;1H\x1b[2K>[2] --> {}\r\n\x1b8", SUB1.name, SUB2.name)),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend("\r".bytes().map(SerialTransaction::read));
+        expectations.extend(vec![
+            SerialTransaction::write_many(format!("\x1b[5;1H\x1b[J\x1b[H\x1b[2J\r\n{}\r\n <0> <-- Back to {}\r\n>[1] r=> {}: 32\r\n [2] w=> {}: 32\r\n",
+                                                   SUB2.name, MAIN_MENU.name, UINT_VAL.name, UINT_VAL_WRITE.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+
+            // The notice is kept below the redrawn menu
+            SerialTransaction::write_many(format!("\x1b[H\x1b[2J\r\n{}\r\n>[1] --> {}\r\n [2] --> {}\r\nInactivity timeout\r\n", MAIN_MENU.name, SUB1.name, SUB2.name)),
+            SerialTransaction::flush(),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+        ]);
+
+        let mut serial = SerialMock::new(&expectations);
+
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        now.set(1000);
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    mitem! {
+        static TREE: MenuItem<Context> = menu("Tree", [
+            read("Uint", |buf, ctx| { let _ = write!(buf, "{}", ctx.uint_value); }, hint = "count"),
+            menu(BRANCH, "Branch", active = |ctx| ctx.bool_value, [
+                write("Uint", |buf, ctx| { let _ = write!(buf, "{}", ctx.uint_value); }, |buf, ctx| {
+                    ctx.uint_value = buf.parse()?;
+                    Ok(())
+                }),
+                exec("Reset", |_, _| {}, |ctx| ctx.uint_value = 0, confirm = Confirm::YesNo, access = AccessLevel::Service),
+            ]),
+        ]);
+    }
+
+    #[test]
+    fn mitem() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        assert!(TREE.parent.is_none());
+        assert_eq!(BRANCH.parent.map(|parent| parent.name), Some(TREE.name));
+        match BRANCH.menu_type {
+            MenuItemType::SubMenu(children, is_active) => {
+                assert!(is_active(&context));
+                assert_eq!(children[0].parent.map(|parent| parent.name), Some(BRANCH.name));
+                assert_eq!(children[1].access, AccessLevel::Service);
+            },
+            _ => panic!("Branch is not a menu"),
+        }
+
+        let mut runner = Dispatcher::new(&TREE);
+
+        let mut expectations = vec![
+            SerialTransaction::write_many(format!("\r\n{}\r\n [1] r=> Uint: 32 [count]\r\n [2] --> {}\r\n", TREE.name, BRANCH.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" <0> --> {}\r\n", MAIN_MENU.name)),
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many(format!("\r\n{}\r\n <0> <-- Back to {}\r\n [1] w=> Uint: 32\r\n [2] e=> Reset: \r\n", BRANCH.name, TREE.name)),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [1] r=> {}: {}\r\n", UINT_VAL.name, 25)),
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many("Uint: 32\r\nEnter new value:\r\n"),
             SerialTransaction::flush(),
-            SerialTransaction::write_many(&format!(" [2] w=> {}: {}\r\n", UINT_VAL_WRITE.name, 25)),
+        ];
+        expectations.extend(type_chars("7"));
+        expectations.extend(vec![
+            SerialTransaction::read(b'\r'),
+            SerialTransaction::write_many(format!("\r\n\r\n{}\r\n <0> <-- Back to {}\r\n [1] w=> Uint: 7\r\n [2] e=> Reset: \r\n", BRANCH.name, TREE.name)),
             SerialTransaction::flush(),
 
             // End Test
             SerialTransaction::read(b'x'),
-        ];
+        ]);
 
         let mut serial = SerialMock::new(&expectations);
 
-        match runner.run(&mut context, &mut serial) {
-            Ok(_) => {}
-            Err(e1) => {
-                if e1 != nb::Error::WouldBlock {
-                    panic!("Error: {:?}", e1);
-                }
-            }
-        }
+        assert_eq!(runner.run(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        serial.done();
+    }
+
+    #[test]
+    fn poll() {
+        let mut context = Context { bool_value: true, uint_value: 32 };
+
+        let mut runner = Dispatcher::new(&MAIN_MENU).without_init();
+
+        let mut expectations = vec![SerialTransaction::read(b'2')];
+        expectations.extend(sub2_menu(32));
+        expectations.extend(vec![
+            SerialTransaction::read(b'2'),
+            SerialTransaction::write_many(format!("{}: {}\r\n", UINT_VAL_WRITE.name, 32)),
+            SerialTransaction::flush(),
+            SerialTransaction::write_many("Enter new value:\r\n"),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(type_chars("4"));
+        expectations.extend(vec![
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+
+            // An escape sequence split over two polls
+            SerialTransaction::read(ESCAPE as u8),
+            SerialTransaction::read_error(nb::Error::WouldBlock),
+            SerialTransaction::read(b'['),
+            SerialTransaction::read(b'D'),
+            SerialTransaction::write(BACKSPACE as u8),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'1'),
+            SerialTransaction::write_many(format!("14{BACKSPACE}")),
+            SerialTransaction::flush(),
+            SerialTransaction::read(b'\r'),
+            SerialTransaction::write_many("\r\n"),
+            SerialTransaction::flush(),
+        ]);
+        expectations.extend(sub2_menu(14));
+        expectations.push(SerialTransaction::read_error(nb::Error::WouldBlock));
+
+        let mut mock = SerialMock::new(&expectations);
+        let mut serial = Lines::new(&mut mock);
+
+        // Every poll returns as soon as the input runs dry, and resumes where it left off
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        assert_eq!(context.uint_value, 32);
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        assert_eq!(runner.poll(&mut context, &mut serial), Err(nb::Error::WouldBlock));
+        assert_eq!(context.uint_value, 14);
+        mock.done();
     }
 }
@@ -5,25 +5,12 @@
 #[macro_export]
 macro_rules! sprint {
     ($stdout:expr, $($arg:tt)*) => ({
-        let mut output: String<U64> = String::new();
-        if core::fmt::write(&mut output, format_args!($($arg)*)).is_ok() {
-
-            #[cfg(test)]
-            {
-                print!("{}", output);
-            }
-
-            for b in output.as_bytes() {
-                $stdout.write(*b)?;
-                if *b == b'\n' {
-                    $stdout.flush()?;
-                }
-            }
-            Ok( () )
-        }
-        else {
-            Err(nb::Error::WouldBlock)
+        #[cfg(test)]
+        {
+            print!($($arg)*);
         }
+
+        $crate::output::write_fmt($stdout, format_args!($($arg)*))
     })
 }
 
@@ -34,35 +21,92 @@ macro_rules! sprintln {
     ($stdout:expr, $fmt:expr)              => { sprint!($stdout, concat!($fmt, "\r\n") ) };
     ($stdout:expr, $fmt:expr, $($arg:tt)*) => { sprint!($stdout, concat!($fmt, "\r\n"), $($arg)*) };
 }
-/*
+/// Build a static menu tree.
+///
+/// Every menu needs a name for its static, the items in it are generated in place. Parent links and child slices are
+/// filled in. Options follow the required arguments in the order shown, and can be left out.
+///
+/// ```
+/// use core::fmt::Write;
+/// use serial_menu::{mitem, AccessLevel, Confirm, MenuItem};
+///
+/// struct Context { count: u32, enabled: bool }
+///
+/// mitem! {
+///     static MAIN: MenuItem<Context> = menu("Main", [
+///         read("Count", |buf, ctx| { let _ = write!(buf, "{}", ctx.count); }, hint = "events"),
+///         menu(SETTINGS, "Settings", active = |ctx| ctx.enabled, [
+///             write("Count", |buf, ctx| { let _ = write!(buf, "{}", ctx.count); }, |arg, ctx| {
+///                 ctx.count = arg.parse()?;
+///                 Ok(())
+///             }, access = AccessLevel::Service),
+///             exec("Reset", |_, _| {}, |ctx| ctx.count = 0, confirm = Confirm::YesNo),
+///         ]),
+///     ]);
+/// }
+/// ```
 #[macro_export]
 macro_rules! mitem {
-    (Main,
-     $ident_name:ident = $name:expr,
-     [$( $child_name:expr => $children:tt, )*]
-    ) => {
-        $(
-            mitem!($childname:expr; $children:tt)
-        )+
+    (@hint) => { None };
+    (@hint $hint:expr) => { Some($hint) };
+    (@access) => { $crate::AccessLevel::User };
+    (@access $access:expr) => { $access };
+    (@active) => { |_| true };
+    (@active $active:expr) => { $active };
+    (@confirm) => { $crate::Confirm::Never };
+    (@confirm $confirm:expr) => { $confirm };
 
-        static $ident_name: MenuItem<'_, Context> = MenuItem {
+    (@menu $vis:vis, $ctx:ty, $id:ident, $parent:expr, $name:expr, ($($hint:expr)?), ($($active:expr)?), ($($access:expr)?),
+     [$($kind:ident $args:tt),* $(,)?]) => {
+        $vis static $id: $crate::MenuItem<'static, $ctx> = $crate::MenuItem {
             name: $name,
-            hint: None,
-            parent: None,
-            menu_type: MenuItemType::SubMenu(&[$( &$childname )+ ]),
+            hint: $crate::mitem!(@hint $($hint)?),
+            access: $crate::mitem!(@access $($access)?),
+            parent: $parent,
+            menu_type: $crate::MenuItemType::SubMenu(&[$($crate::mitem!(@entry $id, $kind $args)),*], $crate::mitem!(@active $($active)?)),
         };
+        $($crate::mitem!(@static $vis, $ctx, $id, $kind $args);)*
+    };
+
+    /* The entry in the child slice of `$parent` */
+    (@entry $parent:ident, menu ($id:ident, $($rest:tt)*)) => { &$id };
+    (@entry $parent:ident, read ($name:expr, $read:expr $(, hint = $hint:expr)? $(, access = $access:expr)? $(,)?)) => {
+        &$crate::MenuItem {
+            name: $name,
+            hint: $crate::mitem!(@hint $($hint)?),
+            access: $crate::mitem!(@access $($access)?),
+            parent: Some(&$parent),
+            menu_type: $crate::MenuItemType::ReadValue($read),
+        }
     };
-    ($ident_name:ident => $parent:expr
-     Read (&$parent:ident),
-      = $name:expr,
-     r=> $rfunc:expr
-    ) => {
-        static $ident_name: MenuItem<'_, Context> = MenuItem {
+    (@entry $parent:ident, write ($name:expr, $read:expr, $write:expr $(, hint = $hint:expr)? $(, access = $access:expr)? $(,)?)) => {
+        &$crate::MenuItem {
             name: $name,
-            hint: None,
+            hint: $crate::mitem!(@hint $($hint)?),
+            access: $crate::mitem!(@access $($access)?),
             parent: Some(&$parent),
-            menu_type: MenuItemType::ReadValue($rfunc),
-        };
+            menu_type: $crate::MenuItemType::WriteValue($read, $write),
+        }
+    };
+    (@entry $parent:ident, exec ($name:expr, $read:expr, $exec:expr $(, confirm = $confirm:expr)? $(, hint = $hint:expr)? $(, access = $access:expr)? $(,)?)) => {
+        &$crate::MenuItem {
+            name: $name,
+            hint: $crate::mitem!(@hint $($hint)?),
+            access: $crate::mitem!(@access $($access)?),
+            parent: Some(&$parent),
+            menu_type: $crate::MenuItemType::ExecValue($read, $exec, $crate::mitem!(@confirm $($confirm)?)),
+        }
+    };
+
+    /* Sub menus get a static of their own, so their items can link back to them */
+    (@static $vis:vis, $ctx:ty, $parent:ident, menu ($id:ident, $name:expr $(, hint = $hint:expr)? $(, active = $active:expr)? $(, access = $access:expr)?,
+     [$($children:tt)*] $(,)?)) => {
+        $crate::mitem!(@menu $vis, $ctx, $id, Some(&$parent), $name, ($($hint)?), ($($active)?), ($($access)?), [$($children)*]);
+    };
+    (@static $vis:vis, $ctx:ty, $parent:ident, $kind:ident $args:tt) => {};
+
+    ($vis:vis static $id:ident: MenuItem<$ctx:ty> = menu($name:expr $(, hint = $hint:expr)? $(, active = $active:expr)? $(, access = $access:expr)?,
+     [$($children:tt)*] $(,)?);) => {
+        $crate::mitem!(@menu $vis, $ctx, $id, None, $name, ($($hint)?), ($($active)?), ($($access)?), [$($children)*]);
     };
 }
-*/
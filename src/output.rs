@@ -0,0 +1,232 @@
+//! Output
+//!
+//! Everything the menu prints is staged in a buffer and handed to the [`Transport`] in bulk. A full buffer is written
+//! out and reused, so output of any length fits, and the transport is only flushed once a render is complete or before
+//! waiting on input.
+
+use core::fmt;
+
+use crate::screen::Fingerprint;
+use crate::{Transport, Word};
+
+/// Output that has been staged but not yet accepted by the transport, kept between steps.
+pub struct Staging<const N: usize> {
+    buffer: [Word; N],
+    /// Staged bytes are `buffer[start..len]`.
+    start: usize,
+    len: usize,
+    /// Bytes were written since the last flush.
+    dirty: bool,
+}
+impl<const N: usize> Staging<N> {
+    pub const fn new() -> Self {
+        Self { buffer: [0; N], start: 0, len: 0, dirty: false }
+    }
+}
+
+/// Holds up to `N` bytes of output, refusing what does not fit.
+pub struct Text<const N: usize> {
+    buffer: [Word; N],
+    len: usize,
+}
+impl<const N: usize> Text<N> {
+    pub const fn new() -> Self {
+        Self { buffer: [0; N], len: 0 }
+    }
+
+    pub fn as_bytes(&self) -> &[Word] {
+        &self.buffer[..self.len]
+    }
+}
+impl<const N: usize> Transport for Text<N> {
+    type Error = fmt::Error;
+
+    fn read(&mut self) -> nb::Result<Word, fmt::Error> {
+        Err(nb::Error::WouldBlock)
+    }
+
+    fn write(&mut self, word: Word) -> nb::Result<(), fmt::Error> {
+        let slot = self.buffer.get_mut(self.len).ok_or(nb::Error::Other(fmt::Error))?;
+        *slot = word;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), fmt::Error> {
+        Ok(())
+    }
+}
+
+/// Stages output for `transport` in a buffer of `N` bytes.
+///
+/// When the transport would block the staged bytes stay where they are, and are written out first the next time.
+pub struct Buffered<'t, T, const N: usize> {
+    transport: &'t mut T,
+    staging: &'t mut Staging<N>,
+}
+impl<'t, T: Transport, const N: usize> Buffered<'t, T, N> {
+    pub const fn new(transport: &'t mut T, staging: &'t mut Staging<N>) -> Self {
+        Self { transport, staging }
+    }
+
+    /// Write out the staged bytes.
+    fn drain(&mut self) -> nb::Result<(), T::Error> {
+        let staging = &mut *self.staging;
+        while staging.start < staging.len {
+            staging.start += self.transport.write_some(&staging.buffer[staging.start..staging.len])?;
+            staging.dirty = true;
+        }
+        staging.start = 0;
+        staging.len = 0;
+        Ok(())
+    }
+
+    /// Write out and flush whatever is left, at the end of a render.
+    pub fn finish(&mut self) -> nb::Result<(), T::Error> {
+        self.drain()?;
+        if self.staging.dirty {
+            self.transport.flush()?;
+            self.staging.dirty = false;
+        }
+        Ok(())
+    }
+}
+impl<T: Transport, const N: usize> Transport for Buffered<'_, T, N> {
+    type Error = T::Error;
+
+    /// Output is made visible before waiting on input, so echoes and prompts show up.
+    fn read(&mut self) -> nb::Result<Word, T::Error> {
+        self.finish()?;
+        self.transport.read()
+    }
+
+    /// Only fails when the buffer is full and the transport would block, in which case `word` is not taken.
+    fn write(&mut self, word: Word) -> nb::Result<(), T::Error> {
+        if self.staging.len == N {
+            self.drain()?;
+        }
+        if N == 0 {
+            self.staging.dirty = true;
+            return self.transport.write(word);
+        }
+        self.staging.buffer[self.staging.len] = word;
+        self.staging.len += 1;
+        Ok(())
+    }
+
+    /// Deferred until the render is finished.
+    fn flush(&mut self) -> nb::Result<(), T::Error> {
+        Ok(())
+    }
+}
+
+/// Skips the part of a render that has already been written, and keeps track of how far it got.
+///
+/// Everything rendered is added to `print`, written or skipped, so it covers the render as a whole.
+pub struct Resume<'t, T> {
+    transport: &'t mut T,
+    skip: usize,
+    pub position: usize,
+    pub print: Fingerprint,
+}
+impl<'t, T> Resume<'t, T> {
+    pub const fn new(transport: &'t mut T, skip: usize, print: Fingerprint) -> Self {
+        Self { transport, skip, position: 0, print }
+    }
+}
+impl<T: Transport> Transport for Resume<'_, T> {
+    type Error = T::Error;
+
+    fn read(&mut self) -> nb::Result<Word, T::Error> {
+        self.transport.read()
+    }
+
+    fn write(&mut self, word: Word) -> nb::Result<(), T::Error> {
+        if self.position >= self.skip {
+            self.transport.write(word)?;
+        }
+        self.position += 1;
+        self.print.add(word);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), T::Error> {
+        self.transport.flush()
+    }
+}
+
+/// Write `words` from `position` on, advancing it past everything the transport took.
+pub fn write_from<T: Transport + ?Sized>(transport: &mut T, words: &[Word], position: &mut usize) -> nb::Result<(), T::Error> {
+    while let Some(rest) = words.get(*position..).filter(|rest| !rest.is_empty()) {
+        *position += transport.write_some(rest)?;
+    }
+    Ok(())
+}
+
+/// Passes formatted text on to a transport, keeping the error `core::fmt` can't carry.
+struct Adapter<'t, T: Transport + ?Sized> {
+    transport: &'t mut T,
+    error: Option<nb::Error<T::Error>>,
+}
+impl<T: Transport + ?Sized> fmt::Write for Adapter<'_, T> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.transport.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// Format `args` onto `transport`, used by [`sprint!`](crate::sprint).
+pub fn write_fmt<T: Transport + ?Sized>(transport: &mut T, args: fmt::Arguments<'_>) -> nb::Result<(), T::Error> {
+    render(transport, |out| out.write_fmt(args))
+}
+
+/// Let `draw` write to `transport` through `core::fmt::Write`.
+///
+/// When `draw` fails by itself, say because a buffer of its own overflowed, the rest of what it draws is dropped. Only
+/// errors of the transport are passed on, as drawing again would just fail again.
+pub fn render<T, F>(transport: &mut T, draw: F) -> nb::Result<(), T::Error>
+where
+    T: Transport + ?Sized,
+    F: FnOnce(&mut dyn fmt::Write) -> fmt::Result
+{
+    let mut adapter = Adapter { transport, error: None };
+    draw(&mut adapter).or_else(|_| adapter.error.take().map_or(Ok(()), Err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FmtTransport;
+    use heapless::{consts::{U2, U128}, String};
+
+    #[test]
+    fn chunking() {
+        let mut transport = FmtTransport::new(String::<U128>::new(), || None);
+        let mut staging = Staging::<8>::new();
+        let mut output = Buffered::new(&mut transport, &mut staging);
+
+        write_fmt(&mut output, format_args!("{} is longer than the buffer\r\n", "This line")).unwrap();
+        assert_eq!(output.transport.writer, "This line is longer than the buf");
+        output.finish().unwrap();
+        assert_eq!(transport.writer, "This line is longer than the buffer\r\n");
+    }
+
+    #[test]
+    fn failing_draw() {
+        let mut transport = FmtTransport::new(String::<U128>::new(), || None);
+
+        /* A draw that fails by itself is cut short rather than retried */
+        let result = render(&mut transport, |out| {
+            out.write_str("cut")?;
+            Err(fmt::Error)
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(transport.writer, "cut");
+
+        /* Errors of the transport still come through */
+        let mut full = FmtTransport::new(String::<U2>::new(), || None);
+        assert_eq!(render(&mut full, |out| out.write_str("cut")), Err(nb::Error::Other(fmt::Error)));
+    }
+}
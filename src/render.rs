@@ -0,0 +1,316 @@
+//! Rendering
+//!
+//! What the menu looks like is up to a [`Renderer`]. The dispatcher hands it headers, entries, values, prompts and
+//! messages, together with the [`Theme`] to style them in. [`Standard`] is the default look, [`Compact`] fits small
+//! terminals.
+
+use core::fmt::{self, Display, Formatter, Write};
+
+use crate::{output, Confirm, Invalid, Style, Theme, Transport};
+
+/// The kind of an item, as shown in a listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    SubMenu,
+    Read,
+    Write,
+    Exec,
+    Select,
+    Toggle,
+}
+
+/// What a listing shows about an item besides its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status<'e> {
+    /// Requires a higher access level, nothing else is given away.
+    Locked,
+    Disabled,
+    /// Accessible, with its current value if it has one.
+    Available(Option<&'e str>),
+}
+
+/// An item in a listing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry<'e> {
+    pub index: usize,
+    /// Number of digits to align the index to.
+    pub width: usize,
+    pub kind: Kind,
+    pub name: &'e str,
+    pub status: Status<'e>,
+    pub hint: Option<&'e str>,
+    /// Selected in full screen mode.
+    pub highlighted: bool,
+}
+
+/// One of the options of a select list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alternative<'c> {
+    pub index: usize,
+    /// Number of digits to align the index to.
+    pub width: usize,
+    pub label: &'c str,
+    /// The option in effect.
+    pub current: bool,
+    /// Selected in full screen mode.
+    pub highlighted: bool,
+}
+
+/// Input the user is asked for.
+#[derive(Debug, Clone, Copy)]
+pub enum Prompt<'p> {
+    /// A new value, entered on the next line.
+    Value { hint: Option<&'p str> },
+    /// A new value for the write only item `name`, entered without echo.
+    Secret { name: &'p str, hint: Option<&'p str> },
+    Password,
+    /// Confirmation of `question` about the item `name`.
+    Confirm { question: fmt::Arguments<'p>, confirm: Confirm, name: &'p str },
+}
+
+/// Why an input was not accepted.
+#[derive(Debug, Clone, Copy)]
+pub struct Rejection<'r> {
+    pub error: Invalid,
+    pub input: &'r str,
+    /// What would have been accepted.
+    pub hint: Option<&'r str>,
+}
+impl Display for Rejection<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.error {
+            Invalid::Parse => return write!(f, "Unable to parse {}", self.input),
+            Invalid::Refused(reason) => return write!(f, "Unable to write {}: {}", self.input, reason),
+            Invalid::OutOfRange => write!(f, "{} is out of range", self.input)?,
+            Invalid::Step => write!(f, "{} is not a valid step", self.input)?,
+            Invalid::Choice => write!(f, "{} is not a valid choice", self.input)?,
+        }
+        self.hint.map_or(Ok(()), |hint| write!(f, " [{hint}]"))
+    }
+}
+
+/// Puts the menu into words, every method defaults to the [`Standard`] look.
+///
+/// Each method writes complete lines unless noted otherwise.
+///
+/// # Errors
+///
+/// Every method passes on the errors of `out`, and has no other reason to fail.
+#[allow(clippy::missing_errors_doc)]
+pub trait Renderer {
+    /// The title of the menu on display.
+    fn header(&self, out: &mut dyn Write, theme: &Theme, title: &str) -> fmt::Result {
+        write!(out, "\r\n{}\r\n", theme.title.paint(title))
+    }
+
+    /// The entry leading back to `parent`, always index 0.
+    fn back(&self, out: &mut dyn Write, theme: &Theme, width: usize, parent: &str, highlighted: bool) -> fmt::Result {
+        write!(out, "{}{} {} {}\r\n", cursor(highlighted), theme.index.paint(format_args!("<{:>w$}>", 0, w = width)),
+               theme.submenu.paint("<--"), highlight(theme, highlighted).paint(format_args!("Back to {parent}")))
+    }
+
+    fn entry(&self, out: &mut dyn Write, theme: &Theme, entry: &Entry<'_>) -> fmt::Result {
+        let (marker, style) = marker(theme, entry.kind);
+        write!(out, "{}{} {} {}", cursor(entry.highlighted),
+               theme.index.paint(format_args!("[{:>w$X}]", entry.index, w = entry.width)), style.paint(marker),
+               highlight(theme, entry.highlighted).paint(entry.name))?;
+
+        match entry.status {
+            Status::Locked => return write!(out, " {}\r\n", theme.disabled.paint("(Locked)")),
+            Status::Disabled => write!(out, " {}", theme.disabled.paint("(Disabled)"))?,
+            Status::Available(Some(value)) => write!(out, ": {value}")?,
+            Status::Available(None) => {},
+        }
+        if let Some(hint) = entry.hint {
+            write!(out, " {}", theme.hint.paint(format_args!("[{hint}]")))?;
+        }
+        out.write_str("\r\n")
+    }
+
+    /// One of the options of a select list.
+    fn choice(&self, out: &mut dyn Write, theme: &Theme, choice: &Alternative<'_>) -> fmt::Result {
+        write!(out, "{}{} ({}) {}\r\n", cursor(choice.highlighted),
+               theme.index.paint(format_args!("[{:>w$X}]", choice.index, w = choice.width)),
+               if choice.current { '*' } else { ' ' }, highlight(theme, choice.highlighted).paint(choice.label))
+    }
+
+    /// The value of an item after selecting or querying it.
+    fn value(&self, out: &mut dyn Write, _theme: &Theme, name: &str, value: &str) -> fmt::Result {
+        write!(out, "{name}: {value}\r\n")
+    }
+
+    /// The value of an item after executing it.
+    fn executed(&self, out: &mut dyn Write, _theme: &Theme, value: &str) -> fmt::Result {
+        write!(out, "> {value}\r\n")
+    }
+
+    /// Ask for input. Apart from [`Prompt::Value`] the input follows on the same line.
+    fn prompt(&self, out: &mut dyn Write, theme: &Theme, prompt: Prompt<'_>) -> fmt::Result {
+        match prompt {
+            Prompt::Value { hint: Some(hint) } => write!(out, "Enter new value: {}\r\n", theme.hint.paint(format_args!("[{hint}]"))),
+            Prompt::Value { hint: None } => out.write_str("Enter new value:\r\n"),
+            Prompt::Secret { name, hint } => {
+                match hint {
+                    Some(hint) => write!(out, "{}: {}\r\n", name, theme.hint.paint(format_args!("[{hint}]")))?,
+                    None => write!(out, "{name}:\r\n")?,
+                }
+                out.write_str("Enter new value: ")
+            },
+            Prompt::Password => out.write_str("Password: "),
+            Prompt::Confirm { question, confirm: Confirm::TypeName, name } => write!(out, "{question} Type '{name}' to confirm: "),
+            Prompt::Confirm { question, .. } => write!(out, "{question} y/N "),
+        }
+    }
+
+    /// Information about what just happened.
+    fn notice(&self, out: &mut dyn Write, _theme: &Theme, message: fmt::Arguments<'_>) -> fmt::Result {
+        write!(out, "{message}\r\n")
+    }
+
+    /// A command that failed.
+    fn error(&self, out: &mut dyn Write, theme: &Theme, message: fmt::Arguments<'_>) -> fmt::Result {
+        write!(out, "{} {}\r\n", theme.error.paint("error:"), message)
+    }
+}
+
+/// Arrow showing the kind of an item, and its style.
+pub const fn marker(theme: &Theme, kind: Kind) -> (&'static str, Style) {
+    match kind {
+        Kind::SubMenu => ("-->", theme.submenu),
+        Kind::Read => ("r=>", theme.read),
+        Kind::Write => ("w=>", theme.write),
+        Kind::Exec => ("e=>", theme.exec),
+        Kind::Select => ("s=>", theme.select),
+        Kind::Toggle => ("t=>", theme.toggle),
+    }
+}
+
+/// Points out the highlighted entry in the otherwise blank first column.
+const fn cursor(highlighted: bool) -> char {
+    if highlighted { '>' } else { ' ' }
+}
+
+const fn highlight(theme: &Theme, highlighted: bool) -> Style {
+    if highlighted { theme.highlight } else { Style::PLAIN }
+}
+
+/// The default look.
+pub struct Standard;
+impl Renderer for Standard {}
+
+/// One short line per entry, without hints:
+///
+/// ```text
+/// Main
+/// 0:..
+/// 1:Settings/
+/// 2:Voltage=3.3
+/// ```
+pub struct Compact;
+
+/// Separates the index from the name, and points out the highlighted entry.
+const fn separator(highlighted: bool) -> char {
+    if highlighted { '>' } else { ':' }
+}
+
+impl Renderer for Compact {
+    fn header(&self, out: &mut dyn Write, theme: &Theme, title: &str) -> fmt::Result {
+        write!(out, "{}\r\n", theme.title.paint(title))
+    }
+
+    fn back(&self, out: &mut dyn Write, theme: &Theme, width: usize, _parent: &str, highlighted: bool) -> fmt::Result {
+        write!(out, "{}{}{}\r\n", theme.index.paint(format_args!("{:>w$}", 0, w = width)), separator(highlighted),
+               theme.submenu.paint(".."))
+    }
+
+    fn entry(&self, out: &mut dyn Write, theme: &Theme, entry: &Entry<'_>) -> fmt::Result {
+        write!(out, "{}{}{}", theme.index.paint(format_args!("{:>w$X}", entry.index, w = entry.width)),
+               separator(entry.highlighted), highlight(theme, entry.highlighted).paint(entry.name))?;
+        match (entry.status, entry.kind) {
+            (Status::Locked | Status::Disabled, _) => write!(out, "{}", theme.disabled.paint("-"))?,
+            (Status::Available(_), Kind::SubMenu) => write!(out, "{}", theme.submenu.paint("/"))?,
+            (Status::Available(Some(value)), _) => write!(out, "={value}")?,
+            (Status::Available(None), _) => {},
+        }
+        out.write_str("\r\n")
+    }
+
+    fn choice(&self, out: &mut dyn Write, theme: &Theme, choice: &Alternative<'_>) -> fmt::Result {
+        write!(out, "{}{}{}{}\r\n", theme.index.paint(format_args!("{:>w$X}", choice.index, w = choice.width)),
+               separator(choice.highlighted), highlight(theme, choice.highlighted).paint(choice.label),
+               if choice.current { "*" } else { "" })
+    }
+
+    fn value(&self, out: &mut dyn Write, _theme: &Theme, name: &str, value: &str) -> fmt::Result {
+        write!(out, "{name}={value}\r\n")
+    }
+}
+
+/// A renderer together with the theme it draws in, writing to a transport.
+#[derive(Clone, Copy)]
+pub struct View<'r> {
+    pub renderer: &'r dyn Renderer,
+    pub theme: &'r Theme,
+}
+impl View<'_> {
+    pub fn header<T: Transport + ?Sized>(self, tx: &mut T, title: &str) -> nb::Result<(), T::Error> {
+        output::render(tx, |out| self.renderer.header(out, self.theme, title))
+    }
+
+    pub fn back<T: Transport + ?Sized>(self, tx: &mut T, width: usize, parent: &str, highlighted: bool) -> nb::Result<(), T::Error> {
+        output::render(tx, |out| self.renderer.back(out, self.theme, width, parent, highlighted))
+    }
+
+    pub fn entry<T: Transport + ?Sized>(self, tx: &mut T, entry: &Entry<'_>) -> nb::Result<(), T::Error> {
+        output::render(tx, |out| self.renderer.entry(out, self.theme, entry))
+    }
+
+    pub fn choice<T: Transport + ?Sized>(self, tx: &mut T, choice: &Alternative<'_>) -> nb::Result<(), T::Error> {
+        output::render(tx, |out| self.renderer.choice(out, self.theme, choice))
+    }
+
+    pub fn value<T: Transport + ?Sized>(self, tx: &mut T, name: &str, value: &str) -> nb::Result<(), T::Error> {
+        output::render(tx, |out| self.renderer.value(out, self.theme, name, value))
+    }
+
+    pub fn executed<T: Transport + ?Sized>(self, tx: &mut T, value: &str) -> nb::Result<(), T::Error> {
+        output::render(tx, |out| self.renderer.executed(out, self.theme, value))
+    }
+
+    pub fn prompt<T: Transport + ?Sized>(self, tx: &mut T, prompt: Prompt<'_>) -> nb::Result<(), T::Error> {
+        output::render(tx, |out| self.renderer.prompt(out, self.theme, prompt))
+    }
+
+    pub fn notice<T: Transport + ?Sized>(self, tx: &mut T, message: fmt::Arguments<'_>) -> nb::Result<(), T::Error> {
+        output::render(tx, |out| self.renderer.notice(out, self.theme, message))
+    }
+
+    pub fn error<T: Transport + ?Sized>(self, tx: &mut T, message: fmt::Arguments<'_>) -> nb::Result<(), T::Error> {
+        output::render(tx, |out| self.renderer.error(out, self.theme, message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::{consts::U128, String};
+
+    #[test]
+    fn compact() {
+        let mut out = String::<U128>::new();
+        let theme = &Theme::PLAIN;
+        let entry = |index, kind, status| Entry { index, width: 1, kind, name: "Item", status, hint: Some("hint"), highlighted: index == 2 };
+
+        Compact.header(&mut out, theme, "Main").unwrap();
+        Compact.back(&mut out, theme, 1, "Root", false).unwrap();
+        Compact.entry(&mut out, theme, &entry(1, Kind::SubMenu, Status::Available(None))).unwrap();
+        Compact.entry(&mut out, theme, &entry(2, Kind::Read, Status::Available(Some("3")))).unwrap();
+        Compact.entry(&mut out, theme, &entry(10, Kind::Write, Status::Locked)).unwrap();
+        assert_eq!(out, "Main\r\n0:..\r\n1:Item/\r\n2>Item=3\r\nA:Item-\r\n");
+
+        let mut out = String::<U128>::new();
+        Standard.entry(&mut out, theme, &entry(10, Kind::Write, Status::Locked)).unwrap();
+        Standard.entry(&mut out, theme, &entry(2, Kind::Read, Status::Available(Some("3")))).unwrap();
+        assert_eq!(out, " [A] w=> Item (Locked)\r\n>[2] r=> Item: 3 [hint]\r\n");
+    }
+}
@@ -0,0 +1,167 @@
+//! Full Screen Mode
+//!
+//! On terminals that answer a cursor position query the current menu is drawn in place on a cleared screen. One entry
+//! is highlighted, the arrow keys move the highlight and enter selects it. Prompts and messages appear below the menu,
+//! and redrawing the menu only touches the entries that changed. Terminals that do not answer stay in line mode.
+//!
+//! The query is made with the cursor in the far corner, so the answer is the size of the terminal. Menus with more
+//! entries than fit scroll along with the highlight.
+
+use core::convert::Infallible;
+use core::fmt::{Display, Formatter};
+
+use crate::{MenuItem, Transport, Word};
+
+/// Asks the terminal where the cursor is after moving it as far down and right as it goes, the answer is
+/// `ESC [ rows ; columns R`.
+pub const QUERY: &str = "\x1b7\x1b[999;999H\x1b[6n\x1b8";
+/// Cursor home and erase the screen.
+pub const CLEAR: &str = "\x1b[H\x1b[2J";
+pub const CLEAR_LINE: &str = "\x1b[2K";
+/// Erase from the cursor to the end of the screen.
+pub const CLEAR_BELOW: &str = "\x1b[J";
+pub const SAVE_CURSOR: &str = "\x1b7";
+pub const RESTORE_CURSOR: &str = "\x1b8";
+
+/// Rows kept free below the menu for prompts and messages.
+pub const STATUS_ROWS: usize = 2;
+
+/// Move the cursor to the start of a row, counting from 1.
+pub struct Goto(pub usize);
+impl Display for Goto {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "\x1b[{};1H", self.0)
+    }
+}
+
+/// Move the cursor to the start of the line a number of rows up.
+pub struct Rewind(pub usize);
+impl Display for Rewind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "\x1b[{}F", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Line mode.
+    Off,
+    /// Line mode until the terminal answers the query.
+    Probe,
+    On,
+}
+
+/// Swallows output, keeping a hash of it and counting the lines, to tell whether an entry changed and how many rows
+/// the header takes.
+///
+/// An entry that changes into something with the same 32 bit hash is not redrawn. With one chance in about four
+/// billion per change that is accepted, rather than keeping a copy of every row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub hash: u32,
+    pub lines: usize,
+}
+impl Fingerprint {
+    pub const fn new() -> Self {
+        /* FNV-1a */
+        Self { hash: 0x811c_9dc5, lines: 0 }
+    }
+
+    pub fn add(&mut self, word: Word) {
+        self.hash = (self.hash ^ u32::from(word)).wrapping_mul(0x0100_0193);
+        if word == b'\n' {
+            self.lines += 1;
+        }
+    }
+}
+impl Transport for Fingerprint {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<Word, Infallible> {
+        Err(nb::Error::WouldBlock)
+    }
+
+    fn write(&mut self, word: Word) -> nb::Result<(), Infallible> {
+        self.add(word);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// What is on screen, remembering the top `ROWS` entries.
+pub struct Screen<'a, C, const ROWS: usize> {
+    pub mode: Mode,
+    /// Index of the highlighted entry, 0 being the way back.
+    pub selected: usize,
+    /// The menu on screen, as long as it is still intact.
+    pub drawn: Option<&'a MenuItem<'a, C>>,
+    /// Rows of the terminal, 0 while unknown.
+    pub height: usize,
+    /// Rows taken by the header.
+    pub top: usize,
+    /// Indices of the first and last entry of the menu.
+    pub lowest: usize,
+    pub highest: usize,
+    /// Indices of the first and last entry on screen.
+    pub first: usize,
+    pub last: usize,
+    /// Fingerprints of the entries on screen from the top, `None` for those not drawn yet.
+    pub rows: [Option<u32>; ROWS],
+}
+impl<C, const ROWS: usize> Screen<'_, C, ROWS> {
+    pub const fn new(mode: Mode) -> Self {
+        Self { mode, selected: 0, drawn: None, height: 0, top: 0, lowest: 0, highest: 0, first: 0, last: 0, rows: [None; ROWS] }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.mode == Mode::On
+    }
+
+    /// Lay out entries `lowest` to `highest` below a header of `top` rows, showing as many as fit from the top.
+    pub fn layout(&mut self, top: usize, lowest: usize, highest: usize) {
+        let fit = match self.height {
+            0 => usize::MAX,
+            height => height.saturating_sub(top + STATUS_ROWS).max(1),
+        };
+        self.top = top;
+        self.lowest = lowest;
+        self.highest = highest;
+        self.first = lowest;
+        self.last = highest.min(lowest.saturating_add(fit - 1));
+        self.rows = [None; ROWS];
+    }
+
+    /// Highlight entry `idx`, scrolling it into view. Returns true when the entries on screen moved.
+    pub const fn select(&mut self, idx: usize) -> bool {
+        let shown = self.last - self.first;
+        self.selected = idx;
+        if idx < self.first {
+            self.first = idx;
+        } else if idx > self.last {
+            self.first = idx - shown;
+        } else {
+            return false;
+        }
+        self.last = self.first + shown;
+        self.rows = [None; ROWS];
+        true
+    }
+
+    /// What is remembered of entry `idx`.
+    pub fn cached(&mut self, idx: usize) -> Option<&mut Option<u32>> {
+        self.rows.get_mut(idx.checked_sub(self.first)?)
+    }
+
+    /// Row of entry `idx`.
+    pub const fn row(&self, idx: usize) -> usize {
+        self.top + 1 + idx - self.first
+    }
+
+    /// Row below the menu, where prompts and messages go.
+    pub const fn status_row(&self) -> usize {
+        self.row(self.last) + 1
+    }
+}
@@ -0,0 +1,69 @@
+//! Navigation Stack
+//!
+//! The menus the dispatcher went through to reach the current item, so the way back does not depend on parent links.
+
+/// Up to `N` items, the oldest is forgotten to make room for a new one.
+pub struct Stack<T, const N: usize> {
+    items: [Option<T>; N],
+    len: usize,
+}
+impl<T: Copy, const N: usize> Stack<T, N> {
+    pub const fn new() -> Self {
+        Self { items: [None; N], len: 0 }
+    }
+
+    pub fn push(&mut self, item: T) {
+        if N == 0 {
+            return;
+        }
+        if self.len == N {
+            self.items.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.items[self.len] = Some(item);
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.len = self.len.checked_sub(1)?;
+        self.items[self.len].take()
+    }
+
+    /// The most recent item.
+    pub fn last(&self) -> Option<T> {
+        self.len.checked_sub(1).and_then(|i| self.items[i])
+    }
+
+    /// The oldest item still remembered.
+    pub fn first(&self) -> Option<T> {
+        self.iter().next()
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+
+    /// Oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.items[..self.len].iter().flatten().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forget_oldest() {
+        let mut stack = Stack::<u8, 2>::new();
+        assert_eq!(stack.last(), None);
+
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+        assert_eq!(stack.first(), Some(2));
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.pop(), None);
+    }
+}
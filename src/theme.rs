@@ -0,0 +1,108 @@
+//! Themes
+//!
+//! Titles, indices, item kinds, disabled items, hints and errors can be styled with ANSI SGR codes. The plain theme
+//! leaves the output untouched, for terminals that do not understand escape codes.
+
+use core::fmt::{Display, Formatter};
+
+/// The parameters of an ANSI SGR sequence, such as `"1;32"` for bold green. Empty leaves the text as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style(pub &'static str);
+impl Style {
+    pub const PLAIN: Self = Self("");
+
+    /// `value` in this style.
+    pub const fn paint<T: Display>(self, value: T) -> Painted<T> {
+        Painted { style: self, value }
+    }
+}
+
+/// A value displayed in a [`Style`].
+pub struct Painted<T> {
+    style: Style,
+    value: T,
+}
+impl<T: Display> Display for Painted<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        if self.style.0.is_empty() {
+            return write!(f, "{}", self.value);
+        }
+        write!(f, "\x1b[{}m{}\x1b[0m", self.style.0, self.value)
+    }
+}
+
+/// The styles used for each part of the menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub title: Style,
+    pub index: Style,
+    /// `-->` and `<--`
+    pub submenu: Style,
+    /// `r=>`
+    pub read: Style,
+    /// `w=>`
+    pub write: Style,
+    /// `e=>`
+    pub exec: Style,
+    /// `s=>`
+    pub select: Style,
+    /// `t=>`
+    pub toggle: Style,
+    /// Disabled and locked items.
+    pub disabled: Style,
+    pub hint: Style,
+    pub error: Style,
+    /// The entry selected in full screen mode.
+    pub highlight: Style,
+}
+impl Theme {
+    /// No styling at all.
+    pub const PLAIN: Self = Self {
+        title: Style::PLAIN,
+        index: Style::PLAIN,
+        submenu: Style::PLAIN,
+        read: Style::PLAIN,
+        write: Style::PLAIN,
+        exec: Style::PLAIN,
+        select: Style::PLAIN,
+        toggle: Style::PLAIN,
+        disabled: Style::PLAIN,
+        hint: Style::PLAIN,
+        error: Style::PLAIN,
+        highlight: Style::PLAIN,
+    };
+
+    /// Colours for terminals with ANSI support.
+    pub const COLOUR: Self = Self {
+        title: Style("1;4"),
+        index: Style("1"),
+        submenu: Style("34"),
+        read: Style("32"),
+        write: Style("33"),
+        exec: Style("35"),
+        select: Style("36"),
+        toggle: Style("36"),
+        disabled: Style("2"),
+        hint: Style("2;3"),
+        error: Style("1;31"),
+        highlight: Style("7"),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::{consts::U32, String};
+    use core::fmt::Write;
+
+    #[test]
+    fn paint() {
+        let mut buf = String::<U32>::new();
+        write!(buf, "{}", Style("1;31").paint("error:")).unwrap();
+        assert_eq!(buf, "\x1b[1;31merror:\x1b[0m");
+
+        let mut buf = String::<U32>::new();
+        write!(buf, "{}", Style::PLAIN.paint(format_args!("[{:>2}]", 1))).unwrap();
+        assert_eq!(buf, "[ 1]");
+    }
+}
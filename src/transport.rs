@@ -0,0 +1,207 @@
+//! Transports
+//!
+//! The menu talks to the terminal through a [`Transport`]. Every embedded-hal 0.2 serial port is a transport as is,
+//! adapters are provided for the `embedded-io` traits and for a `core::fmt::Write` paired with a source of bytes.
+
+use embedded_hal::serial::{Read as HalRead, Write as HalWrite};
+
+use crate::Word;
+
+/// A byte oriented, non-blocking connection to the terminal.
+pub trait Transport {
+    type Error;
+
+    /// Read a byte.
+    ///
+    /// # Errors
+    ///
+    /// `WouldBlock` when none is available, or what went wrong with the connection.
+    fn read(&mut self) -> nb::Result<Word, Self::Error>;
+
+    /// Write a byte.
+    ///
+    /// # Errors
+    ///
+    /// `WouldBlock` when the transport cannot take it yet, or what went wrong with the connection.
+    fn write(&mut self, word: Word) -> nb::Result<(), Self::Error>;
+
+    /// Make sure everything written so far is sent.
+    ///
+    /// # Errors
+    ///
+    /// `WouldBlock` while that is still in progress, or what went wrong with the connection.
+    fn flush(&mut self) -> nb::Result<(), Self::Error>;
+
+    /// Write as many of `words` as the transport takes without blocking, byte by byte unless it can do better.
+    ///
+    /// Returns how many were written.
+    ///
+    /// # Errors
+    ///
+    /// `WouldBlock` only when none of them were, or what went wrong with the connection.
+    fn write_some(&mut self, words: &[Word]) -> nb::Result<usize, Self::Error> {
+        for (written, &word) in words.iter().enumerate() {
+            match self.write(word) {
+                Ok(()) => {},
+                Err(nb::Error::WouldBlock) if written > 0 => return Ok(written),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(words.len())
+    }
+
+    /// Write all of `words`, see [`write_some`](Self::write_some).
+    ///
+    /// # Errors
+    ///
+    /// `WouldBlock` when the transport stopped taking them before the end, or what went wrong with the connection.
+    fn write_all(&mut self, mut words: &[Word]) -> nb::Result<(), Self::Error> {
+        while !words.is_empty() {
+            let written = self.write_some(words)?;
+            words = &words[written..];
+        }
+        Ok(())
+    }
+}
+
+/// Any embedded-hal 0.2 serial port.
+impl<S, E> Transport for S
+where
+    S: HalRead<Word, Error = E> + HalWrite<Word, Error = E>
+{
+    type Error = E;
+
+    fn read(&mut self) -> nb::Result<Word, E> {
+        HalRead::read(self)
+    }
+
+    fn write(&mut self, word: Word) -> nb::Result<(), E> {
+        HalWrite::write(self, word)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), E> {
+        HalWrite::flush(self)
+    }
+}
+
+/// Adapter for the `embedded-io` traits, reads only when [`ReadReady`](embedded_io::ReadReady) says a byte is
+/// waiting so they never block.
+#[cfg(feature = "embedded-io")]
+pub struct EmbeddedIo<T>(pub T);
+#[cfg(feature = "embedded-io")]
+impl<T> Transport for EmbeddedIo<T>
+where
+    T: embedded_io::Read + embedded_io::ReadReady + embedded_io::Write
+{
+    type Error = T::Error;
+
+    fn read(&mut self) -> nb::Result<Word, T::Error> {
+        if !self.0.read_ready()? {
+            return Err(nb::Error::WouldBlock);
+        }
+        let mut word = [0];
+        match self.0.read(&mut word)? {
+            0 => Err(nb::Error::WouldBlock),
+            _ => Ok(word[0]),
+        }
+    }
+
+    fn write(&mut self, word: Word) -> nb::Result<(), T::Error> {
+        Ok(self.0.write_all(&[word])?)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), T::Error> {
+        Ok(self.0.flush()?)
+    }
+
+    fn write_some(&mut self, words: &[Word]) -> nb::Result<usize, T::Error> {
+        Ok(self.0.write(words)?)
+    }
+}
+
+/// Where a [`FmtTransport`] gets its input from.
+pub trait ByteSource {
+    /// The next received byte, if any.
+    fn next_byte(&mut self) -> Option<Word>;
+}
+impl<F: FnMut() -> Option<Word>> ByteSource for F {
+    fn next_byte(&mut self) -> Option<Word> {
+        self()
+    }
+}
+
+/// Adapter for a `core::fmt::Write` as output, and a [`ByteSource`] as input.
+///
+/// Output bytes are collected until they form a complete UTF-8 character.
+pub struct FmtTransport<W, R> {
+    pub writer: W,
+    pub source: R,
+    pending: [u8; 4],
+    len: usize,
+}
+impl<W, R> FmtTransport<W, R> {
+    pub const fn new(writer: W, source: R) -> Self {
+        Self { writer, source, pending: [0; 4], len: 0 }
+    }
+}
+impl<W: core::fmt::Write, R: ByteSource> Transport for FmtTransport<W, R> {
+    type Error = core::fmt::Error;
+
+    fn read(&mut self) -> nb::Result<Word, core::fmt::Error> {
+        self.source.next_byte().ok_or(nb::Error::WouldBlock)
+    }
+
+    fn write(&mut self, word: Word) -> nb::Result<(), core::fmt::Error> {
+        self.pending[self.len] = word;
+        self.len += 1;
+
+        let result = match core::str::from_utf8(&self.pending[..self.len]) {
+            Ok(s) => self.writer.write_str(s),
+            /* Wait for the rest of the character */
+            Err(e) if e.error_len().is_none() => return Ok(()),
+            Err(_) => self.writer.write_char(char::REPLACEMENT_CHARACTER),
+        };
+        /* A character the writer refused is dropped, not sent again with the next one */
+        self.len = 0;
+        Ok(result?)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), core::fmt::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::{consts::{U2, U64}, String};
+
+    #[test]
+    fn fmt_transport() {
+        let mut input = "ok".bytes();
+        let mut transport = FmtTransport::new(String::<U64>::new(), || input.next());
+
+        for word in "Grüße\r\n".bytes() {
+            transport.write(word).unwrap();
+        }
+        transport.write(0xff).unwrap();
+        assert_eq!(transport.writer, "Grüße\r\n\u{fffd}");
+
+        assert_eq!(transport.read(), Ok(b'o'));
+        assert_eq!(transport.read(), Ok(b'k'));
+        assert_eq!(transport.read(), Err(nb::Error::WouldBlock));
+    }
+
+    #[test]
+    fn fmt_transport_full() {
+        let mut transport = FmtTransport::new(String::<U2>::new(), || None);
+
+        for _ in 0..2 {
+            transport.write(b'a').unwrap();
+        }
+        for _ in 0..8 {
+            assert_eq!(transport.write(b'b'), Err(nb::Error::Other(core::fmt::Error)));
+        }
+        assert_eq!(transport.writer, "aa");
+    }
+}
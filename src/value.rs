@@ -0,0 +1,327 @@
+//! Typed Values
+//!
+//! Values of a known type are parsed and validated by the menu itself, the context is only touched once the input
+//! turned out to be valid.
+
+use core::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use crate::{CallbackError, Confirm};
+
+/// Why an input was not accepted, either by a [`TypedValue`] or by a callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invalid {
+    Parse,
+    OutOfRange,
+    Step,
+    Choice,
+    /// The callback refused the value.
+    Refused(CallbackError),
+}
+impl From<CallbackError> for Invalid {
+    fn from(error: CallbackError) -> Self {
+        match error {
+            CallbackError::ParseError => Self::Parse,
+            CallbackError::OutOfRange => Self::OutOfRange,
+            error => Self::Refused(error),
+        }
+    }
+}
+
+/// Numeric types usable in a [`Number`].
+pub trait Numeric: Copy + PartialOrd + FromStr + Display {
+    /// True when `self` can be reached from `min` in steps of `step`.
+    fn on_step(self, min: Self, step: Self) -> bool;
+    /// True when any value within range is valid.
+    fn is_trivial_step(self) -> bool;
+}
+impl Numeric for i32 {
+    fn on_step(self, min: Self, step: Self) -> bool {
+        step.is_trivial_step() || (i64::from(self) - i64::from(min)) % i64::from(step) == 0
+    }
+
+    fn is_trivial_step(self) -> bool {
+        self <= 1
+    }
+}
+impl Numeric for u32 {
+    fn on_step(self, min: Self, step: Self) -> bool {
+        step.is_trivial_step() || self.saturating_sub(min).is_multiple_of(step)
+    }
+
+    fn is_trivial_step(self) -> bool {
+        self <= 1
+    }
+}
+impl Numeric for f32 {
+    /// Allows for the rounding of decimal input, up to a thousandth of a step either way.
+    fn on_step(self, min: Self, step: Self) -> bool {
+        if step.is_trivial_step() {
+            return true;
+        }
+        let rest = (self - min) % step;
+        rest.min(step - rest) <= step / 1000.
+    }
+
+    fn is_trivial_step(self) -> bool {
+        self <= 0.
+    }
+}
+
+/// A number within `min..=max`, in steps of `step` counted from `min`.
+pub struct Number<'a, T, C> {
+    pub min: T,
+    pub max: T,
+    pub step: T,
+    pub unit: Option<&'a str>,
+    pub get: fn(context: &C) -> T,
+    pub set: fn(context: &mut C, value: T) -> Result<(), CallbackError>,
+}
+impl<T: Numeric, C> Number<'_, T, C> {
+    fn write(&self, input: &str, ctx: &mut C) -> Result<(), Invalid> {
+        let value: T = input.trim().parse().map_err(|_| Invalid::Parse)?;
+
+        if !(value >= self.min && value <= self.max) {
+            return Err(Invalid::OutOfRange);
+        }
+        if !value.on_step(self.min, self.step) {
+            return Err(Invalid::Step);
+        }
+
+        Ok((self.set)(ctx, value)?)
+    }
+
+    fn read(&self, buf: &mut dyn Write, ctx: &C) {
+        let _ = write!(buf, "{}", (self.get)(ctx));
+        if let Some(unit) = self.unit {
+            let _ = write!(buf, " {unit}");
+        }
+    }
+
+    fn hint(&self, buf: &mut dyn Write) {
+        let _ = write!(buf, "{}..{}", self.min, self.max);
+        if !self.step.is_trivial_step() {
+            let _ = write!(buf, " step {}", self.step);
+        }
+        if let Some(unit) = self.unit {
+            let _ = write!(buf, " {unit}");
+        }
+    }
+}
+
+/// An on/off value.
+pub struct Flag<C> {
+    pub get: fn(context: &C) -> bool,
+    pub set: fn(context: &mut C, value: bool) -> Result<(), CallbackError>,
+}
+
+impl<C> Flag<C> {
+    fn parse(input: &str) -> Result<bool, Invalid> {
+        let input = input.trim();
+        if ["1", "on", "true", "yes"].iter().any(|s| s.eq_ignore_ascii_case(input)) {
+            Ok(true)
+        } else if ["0", "off", "false", "no"].iter().any(|s| s.eq_ignore_ascii_case(input)) {
+            Ok(false)
+        } else {
+            Err(Invalid::Parse)
+        }
+    }
+}
+
+/// A flag flipped with a single key press.
+pub struct Toggle<'a, C> {
+    /// Labels shown for off and on, defaults to "off" and "on".
+    pub labels: Option<[&'a str; 2]>,
+    /// Ask before flipping, for flags that guard hardware.
+    pub confirm: Confirm,
+    pub flag: Flag<C>,
+}
+impl<'a, C> Toggle<'a, C> {
+    /// Label of `value`.
+    #[must_use]
+    pub fn label(&self, value: bool) -> &'a str {
+        match self.labels {
+            Some(labels) => labels[usize::from(value)],
+            None if value => "on",
+            None => "off",
+        }
+    }
+
+    /// Parse either one of the labels or the usual on/off spellings.
+    ///
+    /// # Errors
+    ///
+    /// [`Invalid::Parse`] when `input` is neither.
+    pub fn parse(&self, input: &str) -> Result<bool, Invalid> {
+        [false, true].iter()
+                     .copied()
+                     .find(|v| self.label(*v).eq_ignore_ascii_case(input.trim()))
+                     .map_or_else(|| Flag::<C>::parse(input), Ok)
+    }
+
+    /// Set the flag from either one of the labels or the usual on/off spellings.
+    ///
+    /// # Errors
+    ///
+    /// Why `input` was not accepted.
+    pub fn write(&self, input: &str, ctx: &mut C) -> Result<(), Invalid> {
+        let value = self.parse(input)?;
+        Ok((self.flag.set)(ctx, value)?)
+    }
+
+    pub fn hint(&self, buf: &mut dyn Write) {
+        let _ = write!(buf, "{}|{}", self.label(false), self.label(true));
+    }
+}
+
+/// One out of a fixed list of labels, identified by its index.
+pub struct Choice<'a, C> {
+    pub choices: &'a [&'a str],
+    pub get: fn(context: &C) -> usize,
+    pub set: fn(context: &mut C, index: usize) -> Result<(), CallbackError>,
+}
+impl<'a, C> Choice<'a, C> {
+    /// Label of the current choice.
+    pub fn label(&self, ctx: &C) -> &'a str {
+        self.choices.get((self.get)(ctx)).copied().unwrap_or("?")
+    }
+
+    /// Select the choice labelled `input`, ignoring case.
+    ///
+    /// # Errors
+    ///
+    /// [`Invalid::Choice`] when no label matches, or why the callback refused the choice.
+    pub fn write(&self, input: &str, ctx: &mut C) -> Result<(), Invalid> {
+        let input = input.trim();
+        let index = self.choices.iter().position(|c| c.eq_ignore_ascii_case(input)).ok_or(Invalid::Choice)?;
+        Ok((self.set)(ctx, index)?)
+    }
+
+    /// All labels, separated by '|'.
+    pub fn hint(&self, buf: &mut dyn Write) {
+        for (i, c) in self.choices.iter().enumerate() {
+            let _ = write!(buf, "{}{}", if i == 0 { "" } else { "|" }, c);
+        }
+    }
+}
+
+/// A value whose input is parsed and validated by the menu.
+pub enum TypedValue<'a, C> {
+    Int(Number<'a, i32, C>),
+    Uint(Number<'a, u32, C>),
+    Float(Number<'a, f32, C>),
+    Bool(Flag<C>),
+    Choice(Choice<'a, C>),
+}
+impl<C> TypedValue<'_, C> {
+    /// Parse and validate `input`, the context is only updated when it is valid.
+    ///
+    /// # Errors
+    ///
+    /// Why `input` was not accepted.
+    pub fn write(&self, input: &str, ctx: &mut C) -> Result<(), Invalid> {
+        match self {
+            TypedValue::Int(number) => number.write(input, ctx),
+            TypedValue::Uint(number) => number.write(input, ctx),
+            TypedValue::Float(number) => number.write(input, ctx),
+            TypedValue::Bool(flag) => Ok((flag.set)(ctx, Flag::<C>::parse(input)?)?),
+            TypedValue::Choice(choice) => choice.write(input, ctx),
+        }
+    }
+
+    pub fn read(&self, buf: &mut dyn Write, ctx: &C) {
+        match self {
+            TypedValue::Int(number) => number.read(buf, ctx),
+            TypedValue::Uint(number) => number.read(buf, ctx),
+            TypedValue::Float(number) => number.read(buf, ctx),
+            TypedValue::Bool(flag) => {
+                let _ = write!(buf, "{}", if (flag.get)(ctx) { "on" } else { "off" });
+            },
+            TypedValue::Choice(choice) => {
+                let _ = write!(buf, "{}", choice.label(ctx));
+            },
+        }
+    }
+
+    /// Describe the valid input.
+    pub fn hint(&self, buf: &mut dyn Write) {
+        match self {
+            TypedValue::Int(number) => number.hint(buf),
+            TypedValue::Uint(number) => number.hint(buf),
+            TypedValue::Float(number) => number.hint(buf),
+            TypedValue::Bool(_) => {
+                let _ = write!(buf, "on|off");
+            },
+            TypedValue::Choice(choice) => choice.hint(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::{consts::U64, String};
+
+    struct Context {
+        value: i32,
+        level: f32,
+    }
+
+    static NUMBER: TypedValue<'_, Context> = TypedValue::Int(Number {
+        min: -10,
+        max: 20,
+        step: 5,
+        unit: Some("mV"),
+        get: |ctx| ctx.value,
+        set: |ctx, value| {
+            ctx.value = value;
+            Ok(())
+        },
+    });
+
+    static LEVEL: TypedValue<'_, Context> = TypedValue::Float(Number {
+        min: -1.,
+        max: 1.,
+        step: 0.1,
+        unit: None,
+        get: |ctx| ctx.level,
+        set: |ctx, level| {
+            ctx.level = level;
+            Ok(())
+        },
+    });
+
+    #[test]
+    fn validate_number() {
+        let mut ctx = Context { value: 0, level: 0. };
+
+        assert_eq!(NUMBER.write("15", &mut ctx), Ok(()));
+        assert_eq!(ctx.value, 15);
+        assert_eq!(NUMBER.write("-5", &mut ctx), Ok(()));
+        assert_eq!(ctx.value, -5);
+
+        assert_eq!(NUMBER.write("25", &mut ctx), Err(Invalid::OutOfRange));
+        assert_eq!(NUMBER.write("-15", &mut ctx), Err(Invalid::OutOfRange));
+        assert_eq!(NUMBER.write("7", &mut ctx), Err(Invalid::Step));
+        assert_eq!(NUMBER.write("five", &mut ctx), Err(Invalid::Parse));
+        assert_eq!(ctx.value, -5);
+
+        let mut hint = String::<U64>::new();
+        NUMBER.hint(&mut hint);
+        assert_eq!(hint, "-10..20 step 5 mV");
+    }
+
+    #[test]
+    fn validate_float() {
+        let mut ctx = Context { value: 0, level: 0. };
+
+        assert_eq!(LEVEL.write("0.3", &mut ctx), Ok(()));
+        assert_eq!(LEVEL.write("-0.7", &mut ctx), Ok(()));
+        assert_eq!(LEVEL.write("1", &mut ctx), Ok(()));
+        assert_eq!(LEVEL.write("0.25", &mut ctx), Err(Invalid::Step));
+        assert_eq!(LEVEL.write("-0.05", &mut ctx), Err(Invalid::Step));
+        assert!((ctx.level - 1.).abs() < f32::EPSILON);
+    }
+}
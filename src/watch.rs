@@ -0,0 +1,48 @@
+//! Watch Mode and Refresh
+//!
+//! `watch <path>` keeps reading a value, or all values in a menu, and redraws them in place until a key is pressed.
+//! The values are read again whenever the dispatcher is polled and the interval has passed on the [`Clock`].
+//!
+//! With a refresh period the menu on screen is kept up to date the same way, while the dispatcher waits for a selection.
+
+use crate::{Clock, MenuItem};
+
+/// Watch configuration.
+pub struct Watch<'a> {
+    pub clock: &'a dyn Clock,
+    /// Time between reads in milliseconds.
+    pub interval_ms: u32,
+}
+
+/// Periodic refresh of the menu.
+pub struct Refresh<'a> {
+    pub clock: &'a dyn Clock,
+    pub period_ms: u32,
+    /// When the menu was last refreshed.
+    pub since: u32,
+}
+impl Refresh<'_> {
+    /// True when the menu is due for a refresh at `now`, which is then taken as the last one.
+    pub const fn is_due(&mut self, now: u32) -> bool {
+        if now.wrapping_sub(self.since) < self.period_ms {
+            return false;
+        }
+        self.since = now;
+        true
+    }
+}
+
+/// The item being watched.
+pub struct Watching<'a, C> {
+    pub item: &'a MenuItem<'a, C>,
+    /// When the values were last read.
+    pub since: u32,
+    /// Number of values on screen.
+    pub lines: usize,
+}
+impl<C> Watching<'_, C> {
+    /// True when the values are due to be read again at `now`.
+    pub const fn is_due(&self, watch: &Watch<'_>, now: u32) -> bool {
+        now.wrapping_sub(self.since) >= watch.interval_ms
+    }
+}